@@ -0,0 +1,53 @@
+
+use asyncworkers::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PRODUCERS: usize = 4;
+const WORKERS: usize = 8;
+const JOBS_PER_PRODUCER: usize = 100_000;
+const ROUNDS: usize = 5;
+
+/// Submit tiny jobs from several producers and time until all ran
+fn run(w: Workers) -> Duration {
+    let count = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let producers: Vec<_> = (0..PRODUCERS).map(|_| {
+        let h = w.handle();
+        let count = Arc::clone(&count);
+        thread::spawn(move || {
+            for _ in 0..JOBS_PER_PRODUCER {
+                let count = Arc::clone(&count);
+                h.execute(move || {
+                    count.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        })
+    }).collect();
+    for p in producers {
+        p.join().unwrap();
+    }
+    w.wait_idle().unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(count.load(Ordering::Relaxed), PRODUCERS * JOBS_PER_PRODUCER);
+    elapsed
+}
+
+fn bench<F: Fn() -> Workers>(name: &str, new: F) {
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        best = best.min(run(new()));
+    }
+    let jobs = (PRODUCERS * JOBS_PER_PRODUCER) as f64;
+    println!("{:<14} best of {}: {:?} ({:.0} jobs/s)",
+             name, ROUNDS, best, jobs / best.as_secs_f64());
+}
+
+fn main() {
+    println!("{} producers, {} workers, {} jobs per producer",
+             PRODUCERS, WORKERS, JOBS_PER_PRODUCER);
+    bench("shared", || Workers::new(WORKERS));
+    bench("work-stealing", || Workers::new_work_stealing(WORKERS));
+}
@@ -0,0 +1,37 @@
+
+use std::io;
+use std::sync::Once;
+
+/// Pin the calling thread to a CPU core
+#[cfg(target_os = "linux")]
+fn set_affinity(core: usize) -> io::Result<()> {
+    // CPU_SET does not check the index against the set
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "core is out of range"));
+    }
+    // SAFETY: the cpu set is a plain bit mask owned by this frame
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "cpu affinity is only supported on linux"))
+}
+
+/// Pin the calling worker to a core, best effort; a failure is
+/// logged once per process and the worker runs unpinned
+pub(crate) fn pin_worker(idx: usize, core: usize) {
+    static WARN: Once = Once::new();
+    if let Err(e) = set_affinity(core) {
+        WARN.call_once(|| {
+            eprintln!("Worker {}: cannot pin to core {}, running unpinned: {}", idx, core, e);
+        });
+    }
+}
@@ -0,0 +1,136 @@
+
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use crate::workers::{ExitHook, PanicHook, Workers};
+
+/// Builder for a worker pool with all tuning knobs
+///
+/// ```
+/// use asyncworkers::*;
+///
+/// let mut w = Workers::builder()
+///     .size(4)
+///     .queue_capacity(64)
+///     .name_prefix("io")
+///     .stack_size(256 * 1024)
+///     .on_panic(|idx, _| eprintln!("job panicked on worker {}", idx))
+///     .build()
+///     .unwrap();
+/// assert_eq!(w.execute_with_result(|| 6 * 7).join(), Ok(42));
+/// ```
+///
+pub struct WorkersBuilder {
+    pub(crate) size: usize,
+    pub(crate) queue_capacity: Option<usize>,
+    pub(crate) name_prefix: String,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) cores: Vec<usize>,
+    pub(crate) work_stealing: bool,
+    pub(crate) rate_limit: u32,
+    pub(crate) panic_hook: Option<PanicHook>,
+    pub(crate) exit_hook: Option<ExitHook>
+}
+
+impl Default for WorkersBuilder {
+    fn default() -> Self {
+        WorkersBuilder {
+            size: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue_capacity: None,
+            name_prefix: "worker".to_string(),
+            stack_size: None,
+            cores: Vec::new(),
+            work_stealing: false,
+            rate_limit: 0,
+            panic_hook: None,
+            exit_hook: None
+        }
+    }
+}
+
+impl WorkersBuilder {
+    /// Create a builder; the pool size defaults to the available
+    /// parallelism with an unbounded queue
+    pub fn new() -> Self {
+        WorkersBuilder::default()
+    }
+
+    /// Number of workers
+    pub fn size(mut self, n: usize) -> Self {
+        self.size = n;
+        self
+    }
+
+    /// Bound the job queue; see `Workers::with_capacity`
+    pub fn queue_capacity(mut self, cap: usize) -> Self {
+        self.queue_capacity = Some(cap);
+        self
+    }
+
+    /// Prefix for worker thread names; see `Workers::with_name_prefix`
+    pub fn name_prefix(mut self, prefix: &str) -> Self {
+        self.name_prefix = prefix.to_string();
+        self
+    }
+
+    /// Stack size of each worker thread in bytes
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Pin workers to CPU cores; see `Workers::with_affinity`
+    pub fn affinity(mut self, cores: &[usize]) -> Self {
+        self.cores = cores.to_vec();
+        self
+    }
+
+    /// Use per-worker deques with work stealing; see
+    /// `Workers::new_work_stealing`. Ignored with a bounded queue.
+    pub fn work_stealing(mut self, enable: bool) -> Self {
+        self.work_stealing = enable;
+        self
+    }
+
+    /// Start at most `max_per_sec` jobs per second; see
+    /// `Workers::with_rate_limit`. Zero, the default, is unlimited.
+    pub fn rate_limit(mut self, max_per_sec: u32) -> Self {
+        self.rate_limit = max_per_sec;
+        self
+    }
+
+    /// Hook called when a job panics; see `Workers::on_panic`
+    pub fn on_panic<F>(mut self, hook: F) -> Self
+        where F: Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static
+    {
+        self.panic_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Hook called on each worker thread right before it exits
+    ///
+    /// The hook receives the worker index once the worker is done
+    /// with jobs, on shutdown or when `Workers::resize` shrinks the
+    /// pool, e.g. to flush or close a thread local connection rather
+    /// than leaving it to process exit.
+    pub fn on_worker_exit<F>(mut self, hook: F) -> Self
+        where F: Fn(usize) + Send + Sync + 'static
+    {
+        self.exit_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Build the pool, returning an error if a worker thread cannot
+    /// be spawned
+    pub fn build(self) -> io::Result<Workers> {
+        Workers::from_builder(self)
+    }
+}
+
+impl Workers {
+    /// Get a builder to configure a new pool
+    pub fn builder() -> WorkersBuilder {
+        WorkersBuilder::new()
+    }
+}
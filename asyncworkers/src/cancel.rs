@@ -0,0 +1,41 @@
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Cooperative cancellation token
+///
+/// Clones share the same flag. A job submitted with a token that is
+/// cancelled before a worker picks it up is skipped; a job that is
+/// already running has to poll `is_cancelled` and stop on its own.
+///
+/// ```
+/// use asyncworkers::*;
+///
+/// let mut w = Workers::new(1);
+/// let token = CancelToken::new();
+/// token.cancel();
+/// let h = w.execute_with_token(&token, || 42);
+/// assert_eq!(h.join(), Err(JobError::Cancelled));
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>
+}
+
+impl CancelToken {
+    /// Create a new token which is not cancelled
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Cancel all jobs carrying this token
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+
+    /// Check whether the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,194 @@
+
+use std::any::Any;
+use std::fmt;
+use std::thread::JoinHandle;
+
+/// Errors reported while tearing down the worker pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkerError {
+    /// The listed workers (by index) panicked and could not be
+    /// joined cleanly
+    Panicked(Vec<usize>),
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerError::Panicked(idx) => write!(f, "workers panicked: {:?}", idx),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+/// Error returned when the pool no longer accepts jobs; the job is
+/// handed back to the caller
+pub struct SubmitError<F>(pub F);
+
+impl<F> fmt::Debug for SubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SubmitError(..)")
+    }
+}
+
+impl<F> fmt::Display for SubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "worker pool is closed")
+    }
+}
+
+impl<F> std::error::Error for SubmitError<F> {}
+
+/// Error returned by `Workers::try_execute_if_idle` when no worker
+/// is free to start the job right away; the job is handed back to
+/// the caller
+pub struct Busy<F>(pub F);
+
+impl<F> fmt::Debug for Busy<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Busy(..)")
+    }
+}
+
+impl<F> fmt::Display for Busy<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no worker is idle")
+    }
+}
+
+impl<F> std::error::Error for Busy<F> {}
+
+/// Errors returned when a job cannot be queued without blocking;
+/// the job is handed back to the caller
+pub enum TrySubmitError<F> {
+    /// The bounded job queue is full
+    Full(F),
+    /// The pool no longer accepts jobs
+    Closed(F),
+}
+
+impl<F> TrySubmitError<F> {
+    /// Take back the job that could not be submitted
+    pub fn into_inner(self) -> F {
+        match self {
+            TrySubmitError::Full(f) | TrySubmitError::Closed(f) => f,
+        }
+    }
+}
+
+impl<F> fmt::Debug for TrySubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySubmitError::Full(_) => write!(f, "Full(..)"),
+            TrySubmitError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<F> fmt::Display for TrySubmitError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySubmitError::Full(_) => write!(f, "job queue is full"),
+            TrySubmitError::Closed(_) => write!(f, "worker pool is closed"),
+        }
+    }
+}
+
+impl<F> std::error::Error for TrySubmitError<F> {}
+
+/// A job panicked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobPanic {
+    /// Index of the worker the job panicked on
+    pub worker: usize,
+    /// The panic message, if the payload was a string
+    pub message: Option<String>,
+}
+
+impl JobPanic {
+    /// Build from a panic payload caught with `catch_unwind`
+    pub(crate) fn from_payload(worker: usize, payload: &(dyn Any + Send)) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            Some(s.to_string())
+        } else {
+            payload.downcast_ref::<String>().cloned()
+        };
+        JobPanic { worker, message }
+    }
+}
+
+impl fmt::Display for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(m) => write!(f, "job panicked on worker {}: {}", self.worker, m),
+            None => write!(f, "job panicked on worker {}", self.worker),
+        }
+    }
+}
+
+impl std::error::Error for JobPanic {}
+
+/// Errors returned by `Workers::execute_blocking`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockingError {
+    /// The job panicked
+    Panicked(JobPanic),
+    /// The pool no longer accepts jobs
+    Closed,
+}
+
+impl fmt::Display for BlockingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockingError::Panicked(p) => p.fmt(f),
+            BlockingError::Closed => write!(f, "worker pool is closed"),
+        }
+    }
+}
+
+impl std::error::Error for BlockingError {}
+
+/// Workers that were still running when a shutdown timed out
+///
+/// Dropping this detaches (leaks) the remaining threads; `join`
+/// waits for them instead.
+pub struct StillRunning {
+    handles: Vec<(usize, JoinHandle<()>)>,
+}
+
+impl StillRunning {
+    pub(crate) fn new(handles: Vec<(usize, JoinHandle<()>)>) -> Self {
+        StillRunning { handles }
+    }
+
+    /// Indices of the workers still running
+    pub fn workers(&self) -> Vec<usize> {
+        self.handles.iter().map(|(idx, _)| *idx).collect()
+    }
+
+    /// Block until the remaining workers exit
+    pub fn join(self) -> Result<(), WorkerError> {
+        let panicked: Vec<usize> = self.handles.into_iter()
+            .filter_map(|(idx, w)| w.join().err().map(|_| idx))
+            .collect();
+        if panicked.is_empty() {
+            Ok(())
+        } else {
+            Err(WorkerError::Panicked(panicked))
+        }
+    }
+}
+
+impl fmt::Debug for StillRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StillRunning").field(&self.workers()).finish()
+    }
+}
+
+impl fmt::Display for StillRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workers still running: {:?}", self.workers())
+    }
+}
+
+impl std::error::Error for StillRunning {}
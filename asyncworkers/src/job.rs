@@ -0,0 +1,254 @@
+
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+use crate::cancel::CancelToken;
+
+/// Handle to the result of a job submitted to the pool
+///
+/// Returned by `Workers::execute_with_result`; the job's return
+/// value is delivered over a oneshot channel once a worker has
+/// run it. Dropping the handle without joining simply discards
+/// the value.
+///
+/// ```
+/// use asyncworkers::*;
+///
+/// let mut w = Workers::new(2);
+/// let h = w.execute_with_result(|| 6 * 7);
+/// assert_eq!(h.join(), Ok(42));
+/// ```
+///
+pub struct JobHandle<T> {
+    rx: Receiver<Result<T, JobError>>,
+    deadline: Option<(Instant, CancelToken)>
+}
+
+/// Reasons a job handle can fail to yield a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobError {
+    /// The job went away without producing a value; either it
+    /// panicked or the pool was torn down before running it
+    Disconnected,
+    /// The job's cancel token was cancelled before it started
+    Cancelled,
+    /// The job did not finish before its deadline
+    Timeout,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Disconnected => write!(f, "job dropped without a result"),
+            JobError::Cancelled => write!(f, "job cancelled"),
+            JobError::Timeout => write!(f, "job timed out"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+impl<T> JobHandle<T> {
+    /// Block until the job has run and return its value
+    ///
+    /// A handle with a deadline gives up once it has passed.
+    pub fn join(self) -> Result<T, JobError> {
+        let (deadline, token) = match &self.deadline {
+            Some(d) => d,
+            None => return self.rx.recv().unwrap_or(Err(JobError::Disconnected)),
+        };
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.rx.recv_timeout(timeout) {
+            Ok(v) => v,
+            Err(RecvTimeoutError::Timeout) => {
+                // skip the job if it has not started yet
+                token.cancel();
+                Err(JobError::Timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(JobError::Disconnected),
+        }
+    }
+
+    /// Poll for the job's value without blocking; `Ok(None)` means
+    /// the job has not finished yet
+    pub fn try_recv(&self) -> Result<Option<T>, JobError> {
+        match self.rx.try_recv() {
+            Ok(v) => v.map(Some),
+            Err(TryRecvError::Empty) => match &self.deadline {
+                Some((deadline, token)) if Instant::now() >= *deadline => {
+                    token.cancel();
+                    Err(JobError::Timeout)
+                }
+                _ => Ok(None),
+            },
+            Err(TryRecvError::Disconnected) => Err(JobError::Disconnected),
+        }
+    }
+}
+
+/// Trigger for a stateful job started with `Workers::execute_stateful`
+///
+/// Every `fire` runs the job's closure once more on the worker that
+/// owns it. Dropping the trigger ends the job and frees the worker.
+pub struct Trigger {
+    tx: Sender<()>
+}
+
+impl Trigger {
+    /// Run the stateful closure again; fails if the job has ended,
+    /// e.g. because the closure panicked
+    pub fn fire(&self) -> Result<(), SendError<()>> {
+        self.tx.send(())
+    }
+}
+
+/// Wrap a stateful closure into a job which runs it once per
+/// trigger until the trigger is dropped
+pub(crate) fn with_trigger<F>(mut work: F) -> (impl FnOnce() + Send + 'static, Trigger)
+    where F: FnMut() + Send + 'static
+{
+    let (tx, rx) = mpsc::channel();
+    let job = move || {
+        while rx.recv().is_ok() {
+            work();
+        }
+    };
+    (job, Trigger { tx })
+}
+
+/// Wrap a job so its return value is sent to a new job handle
+pub(crate) fn with_result<F, T>(work: F) -> (impl FnOnce() + Send + 'static, JobHandle<T>)
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let (tx, rx) = mpsc::channel();
+    let job = move || {
+        // the handle may have been dropped; nobody wants the value
+        let _ = tx.send(Ok(work()));
+    };
+    (job, JobHandle { rx, deadline: None })
+}
+
+/// Wrap a job so it is skipped if the token is cancelled by the
+/// time it is picked up, and its return value is sent to a new
+/// job handle
+pub(crate) fn with_token<F, T>(token: &CancelToken, work: F) -> (impl FnOnce() + Send + 'static, JobHandle<T>)
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let (tx, rx) = mpsc::channel();
+    let token = token.clone();
+    let job = move || {
+        let res = if token.is_cancelled() {
+            Err(JobError::Cancelled)
+        } else {
+            Ok(work())
+        };
+        let _ = tx.send(res);
+    };
+    (job, JobHandle { rx, deadline: None })
+}
+
+/// Wrap a job so its handle stops waiting for the value once the
+/// timeout has elapsed, and the job is skipped if it has not been
+/// picked up by then
+pub(crate) fn with_timeout<F, T>(timeout: Duration, work: F) -> (impl FnOnce() + Send + 'static, JobHandle<T>)
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let token = CancelToken::new();
+    let (job, mut handle) = with_token(&token, work);
+    handle.deadline = Some((Instant::now() + timeout, token));
+    (job, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Workers;
+
+    #[test]
+    fn test_job_handle() {
+        let mut w = Workers::new(2);
+        let h1 = w.execute_with_result(|| (1..=10).sum::<i32>());
+        let h2 = w.execute_with_result(|| "done".to_string());
+        // dropping a handle without joining must not upset the worker
+        drop(w.execute_with_result(|| 1));
+        assert_eq!(h1.join(), Ok(55));
+        assert_eq!(h2.join(), Ok("done".to_string()));
+        // a panicking job never delivers its value
+        let h3 = w.execute_with_result(|| -> i32 { panic!("no value") });
+        assert_eq!(h3.join(), Err(JobError::Disconnected));
+
+        let h = w.execute_with_result(|| 5);
+        drop(w);
+        assert_eq!(h.try_recv(), Ok(Some(5)));
+        assert_eq!(h.try_recv(), Err(JobError::Disconnected));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut w = Workers::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let token = CancelToken::new();
+        // park the only worker so the next jobs stay queued
+        let t = token.clone();
+        let (started_tx, started) = mpsc::channel();
+        let running = w.execute_with_token(&token, move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+            // a running job polls the token cooperatively
+            t.is_cancelled()
+        });
+        let queued = w.execute_with_token(&token, || 1);
+        let other = w.execute_with_token(&CancelToken::new(), || 2);
+        started.recv().unwrap();
+        token.cancel();
+        tx.send(()).unwrap();
+        assert_eq!(running.join(), Ok(true));
+        assert_eq!(queued.join(), Err(JobError::Cancelled));
+        assert_eq!(other.join(), Ok(2));
+    }
+
+    #[test]
+    fn test_trigger() {
+        let mut w = Workers::new(2);
+        let (tx, rx) = mpsc::channel();
+        let mut total = 0;
+        let trigger = w.execute_stateful(move || {
+            // state captured by the closure survives between runs
+            total += 1;
+            tx.send((total, std::thread::current().id())).unwrap();
+        });
+        trigger.fire().unwrap();
+        trigger.fire().unwrap();
+        trigger.fire().unwrap();
+        let runs: Vec<_> = (0..3).map(|_| rx.recv().unwrap()).collect();
+        assert_eq!(runs.iter().map(|r| r.0).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(runs.iter().all(|r| r.1 == runs[0].1));
+        // dropping the trigger frees the worker again
+        drop(trigger);
+        w.wait_idle().unwrap();
+        assert_eq!(w.active(), 0);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut w = Workers::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let slow = w.execute_with_timeout(Duration::from_millis(50), move || {
+            rx.recv().unwrap();
+            1
+        });
+        let queued = w.execute_with_timeout(Duration::from_millis(50), || 2);
+        let start = Instant::now();
+        assert_eq!(slow.join(), Err(JobError::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(queued.join(), Err(JobError::Timeout));
+        // the abandoned job keeps its worker until it returns
+        tx.send(()).unwrap();
+        w.wait_idle().unwrap();
+        let fast = w.execute_with_timeout(Duration::from_secs(5), || 3);
+        assert_eq!(fast.join(), Ok(3));
+    }
+}
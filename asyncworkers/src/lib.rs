@@ -1,3 +1,20 @@
 
 pub mod workers;
+pub mod job;
+pub mod error;
+pub mod cancel;
+pub mod scope;
+pub mod schedule;
+pub mod builder;
+mod timer;
+mod steal;
+mod affinity;
+mod rate;
+mod stats;
 pub use crate::workers::*;
+pub use crate::job::*;
+pub use crate::error::*;
+pub use crate::cancel::*;
+pub use crate::scope::*;
+pub use crate::schedule::*;
+pub use crate::builder::*;
@@ -0,0 +1,70 @@
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Token bucket pacing the jobs started by the workers
+///
+/// The bucket holds up to one second worth of tokens, so an idle
+/// pool may burst that many jobs before being paced. A rate of zero
+/// disables the limit.
+pub(crate) struct RateLimiter {
+    rate: AtomicU32,
+    bucket: Mutex<Bucket>,
+    changed: Condvar
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(0)
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(max_per_sec: u32) -> Self {
+        RateLimiter {
+            rate: AtomicU32::new(max_per_sec),
+            bucket: Mutex::new(Bucket { tokens: max_per_sec as f64, last: Instant::now() }),
+            changed: Condvar::new()
+        }
+    }
+
+    /// Change the rate, waking workers waiting for a token
+    pub(crate) fn set_rate(&self, max_per_sec: u32) {
+        let mut b = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        self.rate.store(max_per_sec, Ordering::SeqCst);
+        b.tokens = b.tokens.min(max_per_sec as f64);
+        self.changed.notify_all();
+    }
+
+    /// Block until a token is available and take it
+    pub(crate) fn acquire(&self) {
+        // fast path for an unlimited pool
+        if self.rate.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut b = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let rate = self.rate.load(Ordering::SeqCst);
+            if rate == 0 {
+                return;
+            }
+            // refill for the time passed, capped at the bucket size
+            let now = Instant::now();
+            let refill = now.duration_since(b.last).as_secs_f64() * rate as f64;
+            b.tokens = (b.tokens + refill).min(rate as f64);
+            b.last = now;
+            if b.tokens >= 1.0 {
+                b.tokens -= 1.0;
+                return;
+            }
+            let wait = Duration::from_secs_f64((1.0 - b.tokens) / rate as f64);
+            b = self.changed.wait_timeout(b, wait).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+}
@@ -0,0 +1,71 @@
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Handle to a recurring job scheduled with `Workers::schedule_every`
+///
+/// Dropping the handle does not cancel the schedule; call `cancel`.
+#[derive(Clone)]
+pub struct ScheduleHandle {
+    periodic: Arc<Periodic>
+}
+
+/// A recurring job shared between the timer and the workers
+pub(crate) struct Periodic {
+    work: Box<dyn Fn() + Send + Sync + 'static>,
+    pub(crate) period: Duration,
+    cancelled: AtomicBool,
+    running: AtomicBool
+}
+
+impl ScheduleHandle {
+    pub(crate) fn new(periodic: Arc<Periodic>) -> Self {
+        ScheduleHandle { periodic }
+    }
+
+    /// Stop future runs; a run already in progress completes
+    pub fn cancel(&self) {
+        self.periodic.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether the schedule has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.periodic.is_cancelled()
+    }
+}
+
+impl Periodic {
+    pub(crate) fn new<F>(period: Duration, work: F) -> Self
+        where F: Fn() + Send + Sync + 'static
+    {
+        Periodic {
+            work: Box::new(work),
+            period,
+            cancelled: AtomicBool::new(false),
+            running: AtomicBool::new(false)
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Get a job for the next run, or `None` if the previous run is
+    /// still in progress and this tick should be skipped
+    pub(crate) fn next_run(self: &Arc<Self>) -> Option<impl FnOnce() + Send + 'static> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        let p = Arc::clone(self);
+        Some(move || {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| (p.work)()));
+            p.running.store(false, Ordering::SeqCst);
+            // let the worker report the panic as usual
+            if let Err(e) = res {
+                panic::resume_unwind(e);
+            }
+        })
+    }
+}
@@ -0,0 +1,174 @@
+
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use crate::workers::Workers;
+
+/// Scope for jobs that borrow from the caller's stack
+///
+/// Created by `Workers::scope`; every job executed through the scope
+/// has finished by the time `scope` returns, so jobs only need to be
+/// `Send`, not `'static`.
+///
+/// ```
+/// use asyncworkers::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// let w = Workers::new(3);
+/// let arr = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+/// let sum = AtomicUsize::new(0);
+/// w.scope(|s| {
+///     for chunk in arr.chunks(4) {
+///         let sum = &sum;
+///         s.execute(move || {
+///             sum.fetch_add(chunk.iter().sum(), Ordering::SeqCst);
+///         });
+///     }
+/// });
+/// assert_eq!(sum.load(Ordering::SeqCst), 55);
+/// ```
+///
+pub struct Scope<'scope, 'env: 'scope> {
+    workers: &'scope Workers,
+    state: Arc<ScopeState>,
+    // invariant lifetimes, as in std::thread::Scope
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>
+}
+
+/// Book keeping of the jobs running in a scope
+struct ScopeState {
+    running: Mutex<usize>,
+    done: Condvar,
+    panicked: AtomicBool
+}
+
+impl ScopeState {
+    /// Count a job of the scope as finished
+    fn finish(&self) {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        *running -= 1;
+        if *running == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    /// Block until every job of the scope has finished
+    fn wait(&self) {
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let _running = self.done.wait_while(running, |n| *n > 0).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Execute work which may borrow from outside the scope
+    ///
+    /// Panics if the pool no longer accepts jobs.
+    pub fn execute<F>(&'scope self, work: F)
+        where F: FnOnce() + Send + 'scope
+    {
+        if self.try_execute(work).is_err() {
+            panic!("worker pool is closed");
+        }
+    }
+
+    /// Execute work which may borrow from outside the scope, failing
+    /// if the pool no longer accepts jobs
+    pub(crate) fn try_execute<F>(&'scope self, work: F) -> Result<(), ()>
+        where F: FnOnce() + Send + 'scope
+    {
+        *self.state.running.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        let state = Arc::clone(&self.state);
+        let work: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            // record the panic; it is raised again when the scope ends
+            if panic::catch_unwind(AssertUnwindSafe(work)).is_err() {
+                state.panicked.store(true, Ordering::SeqCst);
+            }
+            state.finish();
+        });
+        // SAFETY: `Workers::scope` does not return before every job
+        // executed through this scope has run to completion, so
+        // nothing borrowed for 'scope is used after it ends
+        let work = unsafe {
+            std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Box<dyn FnOnce() + Send + 'static>>(work)
+        };
+        // a job that was never queued must not be waited for
+        self.workers.try_send_work(Box::new(work)).map_err(|_| self.state.finish())
+    }
+}
+
+impl Workers {
+    /// Create a scope for executing jobs that borrow local data
+    ///
+    /// Modeled on `std::thread::scope`: all jobs executed through the
+    /// scope are waited for before this returns, even if `f` panics.
+    /// If any of the jobs panicked, this panics once they are done.
+    ///
+    /// Calling this from a job running on the same pool can deadlock
+    /// when every worker ends up waiting on its own scope.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+        where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+    {
+        let scope = Scope {
+            workers: self,
+            state: Arc::new(ScopeState {
+                running: Mutex::new(0),
+                done: Condvar::new(),
+                panicked: AtomicBool::new(false)
+            }),
+            scope: PhantomData,
+            env: PhantomData
+        };
+        let res = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.state.wait();
+        match res {
+            Err(e) => panic::resume_unwind(e),
+            Ok(_) if scope.state.panicked.load(Ordering::SeqCst) => {
+                panic!("a scoped job panicked")
+            }
+            Ok(r) => r,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope() {
+        let w = Workers::new(3);
+        let mut arr = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        // mutate disjoint chunks in place
+        w.scope(|s| {
+            for chunk in arr.chunks_mut(3) {
+                s.execute(move || {
+                    for i in chunk.iter_mut() {
+                        *i *= 2;
+                    }
+                });
+            }
+        });
+        assert_eq!(arr, vec![2, 4, 6, 8, 10, 12, 14, 16, 18, 20]);
+
+        let total = Mutex::new(0);
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            w.scope(|s| {
+                s.execute(|| panic!("bad scoped job"));
+                s.execute(|| *total.lock().unwrap() += 1);
+            })
+        }));
+        // the scope still waits for every job before panicking
+        assert!(res.is_err());
+        assert_eq!(*total.lock().unwrap(), 1);
+
+        // on a closed pool the scope panics instead of hanging
+        w.close_submission();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            w.scope(|s| s.execute(|| *total.lock().unwrap() += 1))
+        }));
+        assert!(res.is_err());
+        assert_eq!(*total.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,79 @@
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Seconds of history kept for the throughput estimate
+const WINDOW: usize = 10;
+
+/// Cumulative job counters and a ring buffer of per second
+/// completion counts
+///
+/// Everything is a relaxed atomic so recording and polling never
+/// block; a count racing with its slot being recycled may be lost,
+/// which is fine for an estimate.
+pub(crate) struct Stats {
+    start: Instant,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    slots: [Slot; WINDOW]
+}
+
+#[derive(Default)]
+struct Slot {
+    second: AtomicU64,
+    count: AtomicU64
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            start: Instant::now(),
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            slots: Default::default()
+        }
+    }
+}
+
+impl Stats {
+    /// Count a finished job
+    pub(crate) fn record(&self, panicked: bool) {
+        if panicked {
+            self.panicked.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        let sec = self.start.elapsed().as_secs();
+        let slot = &self.slots[sec as usize % WINDOW];
+        let seen = slot.second.load(Ordering::Relaxed);
+        // the first job in a new second recycles the slot
+        if seen != sec && slot.second
+            .compare_exchange(seen, sec, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            slot.count.store(0, Ordering::Relaxed);
+        }
+        slot.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn panicked(&self) -> u64 {
+        self.panicked.load(Ordering::Relaxed)
+    }
+
+    /// Jobs finished per second over the last `WINDOW` seconds
+    pub(crate) fn throughput(&self) -> f64 {
+        let elapsed = self.start.elapsed();
+        let sec = elapsed.as_secs();
+        let first = sec.saturating_sub(WINDOW as u64 - 1);
+        let total: u64 = self.slots.iter()
+            .filter(|s| (first..=sec).contains(&s.second.load(Ordering::Relaxed)))
+            .map(|s| s.count.load(Ordering::Relaxed))
+            .sum();
+        let span = elapsed.as_secs_f64() - first as f64;
+        if span > 0.0 { total as f64 / span } else { 0.0 }
+    }
+}
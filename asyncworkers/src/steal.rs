@@ -0,0 +1,167 @@
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::RecvError;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Attempts to find an item before a worker goes to sleep
+const SPINS: usize = 16;
+
+/// Work stealing job queue
+///
+/// Every worker owns a deque; items are pushed to the deques round
+/// robin and a worker pops from the front of its own deque, falling
+/// back to stealing from the back of the others. Workers only share
+/// a lock when the whole queue is empty and they go to sleep.
+pub(crate) struct StealQueue<T> {
+    deques: Vec<Mutex<VecDeque<T>>>,
+    next: AtomicUsize,
+    /// items pushed but not yet popped
+    len: AtomicUsize,
+    /// workers sleeping on `wake`
+    sleepers: AtomicUsize,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+    lock: Mutex<()>,
+    wake: Condvar
+}
+
+/// Sending side of a steal queue; the queue is closed once every
+/// sender has been dropped, like an mpsc channel
+pub(crate) struct StealSender<T> {
+    queue: Arc<StealQueue<T>>
+}
+
+impl<T> StealQueue<T> {
+    /// Create a queue with `n` deques along with its first sender
+    pub(crate) fn new(n: usize) -> (StealSender<T>, Arc<Self>) {
+        let queue = Arc::new(StealQueue {
+            deques: (0..n.max(1)).map(|_| Mutex::new(VecDeque::new())).collect(),
+            next: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            sleepers: AtomicUsize::new(0),
+            senders: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            wake: Condvar::new()
+        });
+        (StealSender { queue: Arc::clone(&queue) }, queue)
+    }
+
+    /// Pop from the worker's own deque or steal from another one
+    fn pop(&self, idx: usize) -> Option<T> {
+        // skip taking the deque locks when there is nothing to find
+        if self.len.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let n = self.deques.len();
+        let own = idx % n;
+        // only ever hold one deque lock at a time
+        let mine = self.deques[own].lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+        let item = mine.or_else(|| {
+            (1..n).find_map(|k| {
+                self.deques[(own + k) % n].lock().unwrap_or_else(|e| e.into_inner()).pop_back()
+            })
+        });
+        if item.is_some() {
+            self.len.fetch_sub(1, Ordering::SeqCst);
+        }
+        item
+    }
+
+    /// Block until an item is available for worker `idx`; fails once
+    /// the queue is closed and drained
+    pub(crate) fn recv(&self, idx: usize) -> Result<T, RecvError> {
+        loop {
+            // spin briefly before going to sleep; waking a sleeping
+            // worker costs far more than a short job
+            for _ in 0..SPINS {
+                if let Some(item) = self.pop(idx) {
+                    return Ok(item);
+                }
+                thread::yield_now();
+            }
+            let guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            // re-check now that pushers can see us sleeping
+            if self.len.load(Ordering::SeqCst) > 0 {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                return Err(RecvError);
+            }
+            let _guard = self.wake.wait(guard).unwrap_or_else(|e| e.into_inner());
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Wake a sleeping worker, if any
+    fn notify(&self, all: bool) {
+        if all || self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            if all {
+                self.wake.notify_all();
+            } else {
+                self.wake.notify_one();
+            }
+        }
+    }
+}
+
+impl<T> StealSender<T> {
+    /// Push an item onto the next deque in round robin order
+    pub(crate) fn push(&self, item: T) {
+        let q = &self.queue;
+        let i = q.next.fetch_add(1, Ordering::Relaxed) % q.deques.len();
+        // count first so a worker never sees more pops than pushes
+        q.len.fetch_add(1, Ordering::SeqCst);
+        q.deques[i].lock().unwrap_or_else(|e| e.into_inner()).push_back(item);
+        q.notify(false);
+    }
+}
+
+impl<T> Clone for StealSender<T> {
+    fn clone(&self) -> Self {
+        self.queue.senders.fetch_add(1, Ordering::SeqCst);
+        StealSender { queue: Arc::clone(&self.queue) }
+    }
+}
+
+impl<T> Drop for StealSender<T> {
+    fn drop(&mut self) {
+        if self.queue.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.queue.closed.store(true, Ordering::SeqCst);
+            self.queue.notify(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steal_queue() {
+        let (tx, q) = StealQueue::new(4);
+        for i in 0..8 {
+            tx.push(i);
+        }
+        // worker 0 drains its own deque first, then steals
+        assert_eq!(q.recv(0), Ok(0));
+        assert_eq!(q.recv(0), Ok(4));
+        let mut rest: Vec<_> = (0..6).map(|_| q.recv(0).unwrap()).collect();
+        rest.sort();
+        assert_eq!(rest, vec![1, 2, 3, 5, 6, 7]);
+
+        // a sleeping worker wakes up on push and on close
+        let q2 = Arc::clone(&q);
+        let t = thread::spawn(move || (q2.recv(1), q2.recv(1)));
+        thread::sleep(std::time::Duration::from_millis(20));
+        tx.push(9);
+        drop(tx);
+        assert_eq!(t.join().unwrap(), (Ok(9), Err(RecvError)));
+    }
+}
@@ -0,0 +1,128 @@
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Single threaded timer
+///
+/// Items are kept in a heap keyed on their deadline and handed to
+/// the `fire` callback, along with their deadline, on the timer
+/// thread once due. The callback may return a new deadline and item
+/// to be scheduled again.
+pub(crate) struct Timer<T> {
+    state: Arc<(Mutex<TimerState<T>>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>
+}
+
+struct TimerState<T> {
+    heap: BinaryHeap<Entry<T>>,
+    seq: u64,
+    stopped: bool
+}
+
+/// Heap entry; ordered so the earliest deadline is on top and
+/// entries with equal deadlines fire in scheduling order
+struct Entry<T> {
+    at: Instant,
+    seq: u64,
+    item: T
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at && self.seq == other.seq
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed to turn the max-heap into a min-heap
+        other.at.cmp(&self.at).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<T> TimerState<T> {
+    fn push(&mut self, at: Instant, item: T) {
+        self.seq += 1;
+        self.heap.push(Entry { at, seq: self.seq, item });
+    }
+}
+
+impl<T: Send + 'static> Timer<T> {
+    /// Start a timer thread with the given name and fire callback
+    pub(crate) fn new<F>(name: String, mut fire: F) -> io::Result<Self>
+        where F: FnMut(Instant, T) -> Option<(Instant, T)> + Send + 'static
+    {
+        let state = Arc::new((
+            Mutex::new(TimerState { heap: BinaryHeap::new(), seq: 0, stopped: false }),
+            Condvar::new()
+        ));
+        let shared = Arc::clone(&state);
+        let thread = thread::Builder::new().name(name).spawn(move || {
+            let (lock, cvar) = &*shared;
+            let mut st = lock.lock().unwrap_or_else(|e| e.into_inner());
+            while !st.stopped {
+                let now = Instant::now();
+                match st.heap.peek() {
+                    None => st = cvar.wait(st).unwrap_or_else(|e| e.into_inner()),
+                    Some(e) if e.at <= now => {
+                        let e = st.heap.pop().unwrap();
+                        // don't hold the lock while firing
+                        drop(st);
+                        let again = fire(e.at, e.item);
+                        st = lock.lock().unwrap_or_else(|e| e.into_inner());
+                        if let Some((at, item)) = again {
+                            st.push(at, item);
+                        }
+                    }
+                    Some(e) => {
+                        let wait = e.at - now;
+                        st = cvar.wait_timeout(st, wait).unwrap_or_else(|e| e.into_inner()).0;
+                    }
+                }
+            }
+        })?;
+        Ok(Timer { state, thread: Some(thread) })
+    }
+}
+
+impl<T> Timer<T> {
+    /// Schedule an item to fire at the given instant
+    pub(crate) fn schedule(&self, at: Instant, item: T) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap_or_else(|e| e.into_inner()).push(at, item);
+        cvar.notify_one();
+    }
+
+    /// Stop the timer thread, dropping every item not yet fired;
+    /// returns the number of items dropped
+    pub(crate) fn stop(&mut self) -> usize {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap_or_else(|e| e.into_inner()).stopped = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let mut st = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let dropped = st.heap.len();
+        st.heap.clear();
+        dropped
+    }
+}
+
+impl<T> Drop for Timer<T> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,7 +1,22 @@
-
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex};
+use std::any::Any;
+use std::cell::Cell;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, RecvError, SendError, TrySendError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use crate::job::{self, JobHandle, Trigger};
+use crate::cancel::CancelToken;
+use crate::error::{WorkerError, SubmitError, TrySubmitError, Busy, BlockingError, JobPanic, StillRunning};
+use crate::timer::Timer;
+use crate::affinity;
+use crate::rate::RateLimiter;
+use crate::stats::Stats;
+use crate::builder::WorkersBuilder;
+use crate::steal::{StealQueue, StealSender};
+use crate::schedule::{Periodic, ScheduleHandle};
 
 /// Asynchronous Worker Pool
 ///
@@ -23,36 +38,513 @@ use std::thread;
 ///
 pub struct Workers {
     pool: Vec<Option<thread::JoinHandle<()>>>,
-    sender: Option<Sender<Work>>
+    sender: Option<JobSender>,
+    receiver: JobReceiver,
+    shared: Arc<Shared>,
+    size: usize,
+    panicked: Vec<usize>,
+    name_prefix: String,
+    timer: Option<Timer<Timed>>,
+    cores: Vec<usize>,
+    stack_size: Option<usize>
 }
 
 /// Generic work definition
-type Work = Box<dyn FnOnce() + Send + 'static>;
+pub(crate) type Work = Box<dyn Job>;
+
+/// A type erased job which can still hand back the original closure
+pub(crate) trait Job: Send {
+    /// Run the job
+    fn run(self: Box<Self>);
+    /// Recover the job for downcasting to its concrete type
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<F> Job for F
+    where F: FnOnce() + Send + 'static
+{
+    fn run(self: Box<Self>) {
+        (*self)()
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Recover the closure from a message that could not be queued
+fn into_closure<F: 'static>(msg: Message) -> F {
+    match msg {
+        Message::Run(work) => *work.into_any().downcast::<F>().unwrap(),
+        Message::Exit => unreachable!(),
+    }
+}
+
+/// Sending side of the job queue; bounded, unbounded or work stealing
+#[derive(Clone)]
+enum JobSender {
+    Unbounded(Sender<Message>),
+    Bounded(SyncSender<Message>),
+    Stealing(StealSender<Message>)
+}
+
+impl JobSender {
+    /// Queue a message, blocking while a bounded queue is full
+    fn send(&self, msg: Message) -> Result<(), SendError<Message>> {
+        match self {
+            JobSender::Unbounded(tx) => tx.send(msg),
+            JobSender::Bounded(tx) => tx.send(msg),
+            JobSender::Stealing(tx) => {
+                tx.push(msg);
+                Ok(())
+            }
+        }
+    }
+
+    /// Queue a message without blocking
+    fn try_send(&self, msg: Message) -> Result<(), TrySendError<Message>> {
+        match self {
+            JobSender::Unbounded(tx) => tx.send(msg).map_err(|e| TrySendError::Disconnected(e.0)),
+            JobSender::Bounded(tx) => tx.try_send(msg),
+            JobSender::Stealing(tx) => {
+                tx.push(msg);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Receiving side of the job queue
+#[derive(Clone)]
+enum JobReceiver {
+    /// A single channel shared by all workers
+    Shared(Arc<Mutex<Receiver<Message>>>),
+    /// Per-worker deques with stealing
+    Stealing(Arc<StealQueue<Message>>)
+}
+
+impl JobReceiver {
+    /// Receive the next message for worker `idx`
+    fn recv(&self, idx: usize) -> Result<Message, RecvError> {
+        match self {
+            // the lock only guards the receiver and is released before
+            // the job runs, so recover from poisoning and keep serving
+            JobReceiver::Shared(rx) => rx.lock().unwrap_or_else(|e| e.into_inner()).recv(),
+            JobReceiver::Stealing(q) => q.recv(idx),
+        }
+    }
+}
+
+/// Messages delivered to the workers over the job channel
+enum Message {
+    /// Execute the work
+    Run(Work),
+    /// Exit the worker loop; used to shrink the pool
+    Exit
+}
+
+/// Jobs held by the timer thread
+enum Timed {
+    /// Run once when due
+    Once(Work),
+    /// Run every period until cancelled
+    Every(Arc<Periodic>)
+}
+
+thread_local! {
+    /// Index of the pool worker running on this thread
+    static WORKER_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+    /// Index of the worker whose spawn fails, set by tests
+    #[cfg(test)]
+    static FAIL_SPAWN: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Index of the worker the calling job runs on
+pub(crate) fn worker_index() -> usize {
+    WORKER_INDEX.with(|w| w.get()).unwrap_or(0)
+}
+
+/// Spawn the thread of worker `idx`; tests can make it fail
+#[cfg_attr(not(test), allow(unused_variables))]
+fn spawn_worker<F>(builder: thread::Builder, idx: usize, f: F) -> io::Result<thread::JoinHandle<()>>
+    where F: FnOnce() + Send + 'static
+{
+    #[cfg(test)]
+    if FAIL_SPAWN.with(|f| f.get()) == Some(idx) {
+        return Err(io::Error::other("injected spawn failure"));
+    }
+    builder.spawn(f)
+}
+
+/// Hook invoked with the worker index and payload of a job panic
+pub(crate) type PanicHook = Arc<dyn Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static>;
+
+/// Hook invoked with the worker index as a worker exits
+pub(crate) type ExitHook = Arc<dyn Fn(usize) + Send + Sync + 'static>;
+
+/// State shared between the pool and its worker threads
+#[derive(Default)]
+struct Shared {
+    panic_hook: Mutex<Option<PanicHook>>,
+    exit_hook: Option<ExitHook>,
+    /// jobs submitted but not yet picked up by a worker
+    pending: AtomicUsize,
+    /// jobs currently executing
+    active: AtomicUsize,
+    /// signalled when the pool becomes idle
+    idle_lock: Mutex<()>,
+    idle: Condvar,
+    /// workers hold off starting new jobs while set
+    paused: AtomicBool,
+    pause_lock: Mutex<()>,
+    resumed: Condvar,
+    /// record the first job panic for `wait_idle`
+    propagate_panics: AtomicBool,
+    first_panic: Mutex<Option<JobPanic>>,
+    live: AtomicUsize,
+    /// live workers with an exit request queued or taken
+    exiting: AtomicUsize,
+    rate: RateLimiter,
+    closed: AtomicBool,
+    stats: Stats
+}
+
+/// Keeps a worker counted as live until its thread exits, even
+/// when it unwinds; set once the worker took an exit request
+struct Live(Arc<Shared>, bool);
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        self.0.live.fetch_sub(1, Ordering::SeqCst);
+        if self.1 {
+            self.0.exiting.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Shared {
+    /// No job is queued or running
+    fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) == 0 && self.active.load(Ordering::SeqCst) == 0
+    }
+
+    /// Queue a job, blocking while a bounded queue is full
+    fn send_job(&self, sender: &JobSender, work: Work) -> Result<(), Message> {
+        // count the job before a worker can pick it up
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        // send job in the channel; first one to receive will execute
+        sender.send(Message::Run(work)).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            e.0
+        })
+    }
+
+    /// Queue a job, handing it back if the pool is closed
+    fn submit<F>(&self, sender: &JobSender, work: F) -> Result<(), SubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(SubmitError(work));
+        }
+        self.send_job(sender, Box::new(work)).map_err(|msg| SubmitError(into_closure(msg)))
+    }
+
+    /// Queue a job without blocking, handing it back on failure
+    fn try_submit<F>(&self, sender: &JobSender, work: F) -> Result<(), TrySubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(TrySubmitError::Closed(work));
+        }
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        sender.try_send(Message::Run(Box::new(work))).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            match e {
+                TrySendError::Full(msg) => TrySubmitError::Full(into_closure(msg)),
+                TrySendError::Disconnected(msg) => TrySubmitError::Closed(into_closure(msg)),
+            }
+        })
+    }
+
+    /// Queue a job only if a worker is free to start it right away,
+    /// handing it back otherwise
+    fn submit_if_idle<F>(&self, sender: &JobSender, work: F) -> Result<(), Busy<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        if self.closed.load(Ordering::SeqCst) || self.paused.load(Ordering::SeqCst) {
+            return Err(Busy(work));
+        }
+        // claim an idle worker by counting the job as pending, so
+        // concurrent callers can't claim the same one; workers on
+        // their way out of a shrinking pool are not free
+        let claimed = self.pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+            let busy = pending + self.active.load(Ordering::SeqCst);
+            let free = self.live.load(Ordering::SeqCst)
+                .saturating_sub(self.exiting.load(Ordering::SeqCst));
+            (busy < free).then_some(pending + 1)
+        });
+        if claimed.is_err() {
+            return Err(Busy(work));
+        }
+        sender.try_send(Message::Run(Box::new(work))).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            match e {
+                TrySendError::Full(msg) | TrySendError::Disconnected(msg) => Busy(into_closure(msg)),
+            }
+        })
+    }
+
+    /// Block the calling worker while the pool is paused
+    fn wait_resumed(&self) {
+        if self.paused.load(Ordering::SeqCst) {
+            let guard = self.pause_lock.lock().unwrap_or_else(|e| e.into_inner());
+            let _guard = self.resumed
+                .wait_while(guard, |_| self.paused.load(Ordering::SeqCst))
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Let paused workers continue
+    fn resume(&self) {
+        let _guard = self.pause_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_all();
+    }
+
+    /// Called by a worker when it finishes a job
+    fn job_done(&self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        if self.is_idle() {
+            // take the lock so a waiter can't miss the wakeup
+            let _guard = self.idle_lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.idle.notify_all();
+        }
+    }
+
+    /// Report a job panic caught on a worker
+    fn job_panicked(&self, idx: usize, payload: Box<dyn Any + Send>) {
+        if self.propagate_panics.load(Ordering::Relaxed) {
+            self.first_panic.lock().unwrap_or_else(|e| e.into_inner())
+                .get_or_insert_with(|| JobPanic::from_payload(idx, &*payload));
+        }
+        // don't hold the lock while running the hook
+        let hook = self.panic_hook.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        match hook {
+            Some(hook) => hook(idx, payload),
+            None => eprintln!("Worker {}: job panicked", idx),
+        }
+    }
+}
+
+/// Cloneable handle for submitting work to a pool
+///
+/// Obtained from `Workers::handle`; it only allows submitting jobs
+/// and can be sent to other threads. The pool itself stays owned by
+/// the `Workers` value, but its workers keep running until every
+/// handle is gone, so dropping the pool blocks while handles are
+/// still alive.
+#[derive(Clone)]
+pub struct WorkerHandle {
+    sender: JobSender,
+    shared: Arc<Shared>
+}
+
+impl WorkerHandle {
+    /// Execute work on the pool; panics if the pool is closed
+    pub fn execute<F>(&self, work: F)
+        where F: FnOnce() + Send + 'static
+    {
+        self.submit(work).unwrap();
+    }
+
+    /// Execute work on the pool; see `Workers::submit`
+    pub fn submit<F>(&self, work: F) -> Result<(), SubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        self.shared.submit(&self.sender, work)
+    }
+
+    /// Try to execute work without blocking; see `Workers::try_execute`
+    pub fn try_execute<F>(&self, work: F) -> Result<(), TrySubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        self.shared.try_submit(&self.sender, work)
+    }
+}
 
 impl Workers {
     /// Create a new worker pool of given size
+    ///
+    /// Panics if a worker thread cannot be spawned; see `try_new`.
     pub fn new(sz: usize) -> Self {
-        // create a thread pool
-        let mut pool = Vec::with_capacity(sz);
+        Workers::try_new(sz).unwrap()
+    }
+
+    /// Create a new worker pool of given size, returning an error if
+    /// a worker thread cannot be spawned
+    ///
+    /// Either every worker starts or none is left running: should a
+    /// spawn fail, e.g. under a container's thread or pid limit, the
+    /// workers already started are shut down and joined before the
+    /// error is returned, rather than handing out an under-provisioned
+    /// pool whose jobs could queue forever.
+    pub fn try_new(sz: usize) -> io::Result<Self> {
+        Workers::builder().size(sz).build()
+    }
+
+    /// Create a new worker pool of given size whose threads are
+    /// named `<prefix>-<index>`
+    ///
+    /// Named threads show up in debuggers, `/proc/<pid>/task`, panic
+    /// messages and profiles, which helps when several pools coexist.
+    pub fn with_name_prefix(sz: usize, prefix: &str) -> io::Result<Self> {
+        Workers::builder().size(sz).name_prefix(prefix).build()
+    }
+
+    /// Create a new work stealing worker pool of given size
+    ///
+    /// Instead of all workers contending on one shared queue, each
+    /// worker gets its own deque. Jobs are distributed round robin
+    /// and idle workers steal from the back of busy workers' deques,
+    /// which cuts lock contention under high submission rates. Jobs
+    /// are no longer started in strict submission order. The queue
+    /// is unbounded; workers added by `resize` have no deque of
+    /// their own and share one with an existing worker.
+    pub fn new_work_stealing(sz: usize) -> Self {
+        Workers::builder().size(sz).work_stealing(true).build().unwrap()
+    }
+
+    /// Create a new worker pool of given size with a bounded job queue
+    ///
+    /// Once `queue_cap` jobs are waiting, `execute` blocks until a
+    /// worker frees a slot, so a fast producer is slowed down to the
+    /// pace of the pool; `try_execute` hands the job back instead.
+    ///
+    /// A job that itself submits work to a full bounded pool blocks
+    /// its worker; if every worker does so the pool deadlocks. Jobs
+    /// must not wait on the queue they are draining.
+    pub fn with_capacity(sz: usize, queue_cap: usize) -> Self {
+        Workers::builder().size(sz).queue_capacity(queue_cap).build().unwrap()
+    }
+
+    /// Create a new worker pool of given size with each worker
+    /// pinned to a CPU core
+    ///
+    /// Worker `i` is pinned to `cores[i % cores.len()]`, so a short
+    /// list is used round robin. Pinning is best effort and Linux
+    /// only: where it is unsupported or fails, a warning is logged
+    /// once and the workers run unpinned.
+    pub fn with_affinity(sz: usize, cores: &[usize]) -> io::Result<Self> {
+        Workers::builder().size(sz).affinity(cores).build()
+    }
+
+    /// Create a new worker pool of given size starting at most
+    /// `max_per_sec` jobs per second
+    ///
+    /// Workers take a token from a bucket before running a job; the
+    /// bucket holds one second worth of tokens, so up to
+    /// `max_per_sec` jobs may start back to back after a quiet
+    /// period. Waiting jobs still count as pending. See `set_rate`.
+    pub fn with_rate_limit(sz: usize, max_per_sec: u32) -> Self {
+        Workers::builder().size(sz).rate_limit(max_per_sec).build().unwrap()
+    }
+
+    /// Create a pool from a builder configuration
+    pub(crate) fn from_builder(b: WorkersBuilder) -> io::Result<Self> {
         // create job channel
-        let (tx, rx): (Sender<Work>, Receiver<Work>) = mpsc::channel();
+        let (sender, receiver) = match (b.queue_capacity, b.work_stealing) {
+            (Some(cap), _) => {
+                let (tx, rx) = mpsc::sync_channel(cap);
+                (JobSender::Bounded(tx), Workers::shared_receiver(rx))
+            }
+            (None, true) => {
+                let (tx, rx) = StealQueue::new(b.size);
+                (JobSender::Stealing(tx), JobReceiver::Stealing(rx))
+            }
+            (None, false) => {
+                let (tx, rx): (Sender<Message>, Receiver<Message>) = mpsc::channel();
+                (JobSender::Unbounded(tx), Workers::shared_receiver(rx))
+            }
+        };
+        let shared = Shared {
+            panic_hook: Mutex::new(b.panic_hook),
+            exit_hook: b.exit_hook,
+            rate: RateLimiter::new(b.rate_limit),
+            ..Shared::default()
+        };
+
+        let mut w = Workers {
+            // create a thread pool
+            pool: Vec::with_capacity(b.size),
+            sender: Some(sender),
+            receiver,
+            shared: Arc::new(shared),
+            size: 0,
+            panicked: Vec::new(),
+            name_prefix: b.name_prefix,
+            timer: None,
+            cores: b.cores,
+            stack_size: b.stack_size
+        };
+        // create the threads in the pool; on failure the workers
+        // already started are shut down as `w` is dropped
+        w.grow(b.size)?;
+        Ok(w)
+    }
+
+    fn shared_receiver(rx: Receiver<Message>) -> JobReceiver {
         // since reciever will be used from multiple threads
         // from the pool, wrap it in Arc+Mutex for synchronized
         // access
-        let rx = Arc::new(Mutex::new(rx));
+        JobReceiver::Shared(Arc::new(Mutex::new(rx)))
+    }
 
-        // create the threads in the pool
-        for idx in 0..sz {
-            let receiver = Arc::clone(&rx);
-            let worker = thread::spawn( move || {
+    /// Spawn `n` more workers into the pool
+    fn grow(&mut self, n: usize) -> io::Result<()> {
+        for _ in 0..n {
+            let idx = self.pool.len();
+            let receiver = self.receiver.clone();
+            let shared = Arc::clone(&self.shared);
+            let name = format!("{}-{}", self.name_prefix, idx);
+            let core = (!self.cores.is_empty()).then(|| self.cores[idx % self.cores.len()]);
+            let mut builder = thread::Builder::new().name(name);
+            if let Some(bytes) = self.stack_size {
+                builder = builder.stack_size(bytes);
+            }
+            // count the worker before it can possibly exit
+            self.shared.live.fetch_add(1, Ordering::SeqCst);
+            let live = Live(Arc::clone(&self.shared), false);
+            let worker = spawn_worker(builder, idx, move || {
+                let mut live = live;
+                if let Some(core) = core {
+                    affinity::pin_worker(idx, core);
+                }
+                WORKER_INDEX.with(|w| w.set(Some(idx)));
                 println!("Worker {}: Ready", idx);
                 loop {
                     // receive work and execute; exit if channel is closed
-                    match receiver.lock().unwrap().recv() {
-                        Ok(work) => {
+                    let msg = receiver.recv(idx);
+                    match msg {
+                        Ok(Message::Run(work)) => {
+                            // hold on to the job while paused; it still
+                            // counts as pending until it starts
+                            shared.wait_resumed();
+                            shared.rate.acquire();
                             #[cfg(Debug)]
                             println!("Worker {}: Executing...", idx);
-                            work();
+                            shared.active.fetch_add(1, Ordering::Relaxed);
+                            shared.pending.fetch_sub(1, Ordering::Relaxed);
+                            // a panicking job must not take the worker down
+                            let res = panic::catch_unwind(AssertUnwindSafe(|| work.run()));
+                            shared.stats.record(res.is_err());
+                            if let Err(e) = res {
+                                shared.job_panicked(idx, e);
+                            }
+                            shared.job_done();
+                        }
+                        Ok(Message::Exit) => {
+                            live.1 = true;
+                            break;
                         }
                         Err(e) => {
                             eprintln!("{}", e);
@@ -60,31 +552,546 @@ impl Workers {
                         }
                     }
                 }
-
+                if let Some(hook) = &shared.exit_hook {
+                    hook(idx);
+                }
             });
+            // the closure, and with it the live guard, is dropped
+            // if the thread could not be spawned
+            let worker = worker?;
             // add thread to pool
-            pool.push(Some(worker));
+            self.pool.push(Some(worker));
+            self.size += 1;
+        }
+        Ok(())
+    }
+
+    /// Number of workers the pool is sized for
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Resize the pool
+    ///
+    /// Growing spawns new workers right away. Shrinking queues one
+    /// exit request per surplus worker behind the jobs already
+    /// submitted, so no queued job is ever dropped; the first
+    /// workers to pick up an exit request leave the pool. On a
+    /// bounded pool shrinking may block until there is room in the
+    /// queue for the exit requests. Fails if a new worker thread
+    /// cannot be spawned, leaving the workers started so far.
+    pub fn resize(&mut self, new_size: usize) -> io::Result<()> {
+        // reap workers that already left after an earlier shrink
+        self.reap();
+        if new_size > self.size {
+            self.grow(new_size - self.size)?;
+        } else if let Some(sender) = self.sender.as_ref() {
+            for _ in new_size..self.size {
+                self.shared.exiting.fetch_add(1, Ordering::SeqCst);
+                sender.send(Message::Exit).unwrap();
+            }
+            self.size = new_size;
         }
-        Workers { pool, sender: Some(tx) }
+        Ok(())
+    }
+
+    /// Join workers that have already exited
+    fn reap(&mut self) {
+        for (idx, w) in self.pool.iter_mut().enumerate() {
+            if w.as_ref().is_some_and(|w| w.is_finished())
+                && w.take().unwrap().join().is_err()
+            {
+                self.panicked.push(idx);
+            }
+        }
+    }
+
+    /// Set a hook to be called when a job panics
+    ///
+    /// The hook runs on the worker that caught the panic and receives
+    /// the worker index and the panic payload. Without a hook the
+    /// panic is only logged.
+    pub fn on_panic<F>(&mut self, hook: F)
+        where F: Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static
+    {
+        *self.shared.panic_hook.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(hook));
     }
 
+    /// Execute work; panics if the pool no longer accepts jobs
     pub fn execute<F>(&mut self, work: F)
         where F: FnOnce() + Send + 'static
     {
-        // send job in the channel; first one to receive will execute
-        self.sender.as_ref().unwrap().send(Box::new(work)).unwrap();
+        self.send_work(Box::new(work));
     }
-}
 
-/// Graceful shutdown and cleanup
-impl Drop for Workers {
-    fn drop(&mut self) {
+    /// Queue a job, blocking while a bounded queue is full
+    pub(crate) fn send_work(&self, work: Work) {
+        if self.try_send_work(work).is_err() {
+            panic!("worker pool is closed");
+        }
+    }
+
+    /// Queue a job, handing it back if the pool is closed
+    pub(crate) fn try_send_work(&self, work: Work) -> Result<(), Work> {
+        match self.sender.as_ref() {
+            Some(sender) if !self.shared.closed.load(Ordering::SeqCst) => {
+                self.shared.send_job(sender, work).map_err(|msg| match msg {
+                    Message::Run(work) => work,
+                    Message::Exit => unreachable!(),
+                })
+            }
+            _ => Err(work),
+        }
+    }
+
+    /// Execute work, handing it back if the pool no longer accepts
+    /// jobs
+    ///
+    /// Unlike `execute` this never panics, which matters for a
+    /// producer racing with the pool being shut down; the returned
+    /// closure can be retried elsewhere, run inline or dropped.
+    /// Blocks while a bounded queue is full.
+    pub fn submit<F>(&self, work: F) -> Result<(), SubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        match self.sender.as_ref() {
+            Some(sender) => self.shared.submit(sender, work),
+            None => Err(SubmitError(work)),
+        }
+    }
+
+    /// Try to execute work without blocking
+    ///
+    /// If the bounded job queue is full the work is handed back in
+    /// the error so the caller can retry, run it inline or drop it.
+    pub fn try_execute<F>(&self, work: F) -> Result<(), TrySubmitError<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        match self.sender.as_ref() {
+            Some(sender) => self.shared.try_submit(sender, work),
+            None => Err(TrySubmitError::Closed(work)),
+        }
+    }
+
+    /// Execute work only if a worker is idle to start it right away
+    ///
+    /// For latency sensitive callers which would rather not queue
+    /// behind a backlog: if every worker is busy, or the pool is
+    /// paused or closed, the work is handed back in `Busy` so the
+    /// caller can run it inline or shed it. Jobs claim idle workers
+    /// atomically, so concurrent callers never count on the same one,
+    /// and workers leaving a shrinking pool are not counted as idle.
+    pub fn try_execute_if_idle<F>(&self, work: F) -> Result<(), Busy<F>>
+        where F: FnOnce() + Send + 'static
+    {
+        match self.sender.as_ref() {
+            Some(sender) => self.shared.submit_if_idle(sender, work),
+            None => Err(Busy(work)),
+        }
+    }
+
+    /// Stop accepting new jobs while the queued ones are drained
+    ///
+    /// From now on `submit` and `try_execute` hand jobs back as
+    /// closed, on the pool and on all its handles, and `execute`
+    /// panics as it does on a shut down pool. Workers keep running
+    /// the jobs already queued, as well as delayed and recurring
+    /// jobs scheduled before, until `shutdown` joins them.
+    pub fn close_submission(&self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Get a cloneable handle for submitting work from elsewhere
+    pub fn handle(&self) -> WorkerHandle {
+        WorkerHandle {
+            sender: self.sender.clone().unwrap(),
+            shared: Arc::clone(&self.shared)
+        }
+    }
+
+    /// Number of jobs submitted but not yet picked up by a worker
+    ///
+    /// This is a relaxed atomic load and never touches the job
+    /// queue, so it is cheap to poll.
+    pub fn pending(&self) -> usize {
+        self.shared.pending.load(Ordering::Relaxed)
+    }
+
+    /// Number of jobs currently executing
+    pub fn active(&self) -> usize {
+        self.shared.active.load(Ordering::Relaxed)
+    }
+
+    /// Change the rate limit to `max_per_sec` jobs per second
+    ///
+    /// Takes effect for the next job to start, including ones
+    /// already waiting for a token. Zero removes the limit; any
+    /// pool can be limited this way, not only one created with
+    /// `with_rate_limit`.
+    pub fn set_rate(&self, max_per_sec: u32) {
+        self.shared.rate.set_rate(max_per_sec);
+    }
+
+    /// Number of jobs that have run to completion
+    pub fn completed(&self) -> u64 {
+        self.shared.stats.completed()
+    }
+
+    /// Number of jobs that have panicked
+    pub fn panicked(&self) -> u64 {
+        self.shared.stats.panicked()
+    }
+
+    /// Jobs finished per second, completed or panicked, averaged
+    /// over the last ten seconds
+    pub fn throughput_per_sec(&self) -> f64 {
+        self.shared.stats.throughput()
+    }
+
+    /// Number of worker threads still running their loop
+    pub fn live_workers(&self) -> usize {
+        self.shared.live.load(Ordering::SeqCst)
+    }
+
+    /// Whether any worker is left to run jobs
+    ///
+    /// Returns false once every worker thread has finished, e.g.
+    /// after the pool was resized to zero. Jobs submitted to such a
+    /// pool are never run; a supervisor can rebuild it instead.
+    pub fn healthy(&self) -> bool {
+        self.live_workers() > 0
+    }
+
+    /// Pause the pool
+    ///
+    /// Workers finish the job they are running but start no new
+    /// ones until `resume` is called; submitted jobs stay queued.
+    /// Shutting down resumes the pool so the queue can drain.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume a paused pool
+    pub fn resume(&self) {
+        self.shared.resume();
+    }
+
+    /// Check whether the pool is paused
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    /// Record job panics so they surface from `wait_idle`
+    ///
+    /// When enabled, the first job panic caught since the last
+    /// `wait_idle` is kept and returned from it as an error, which
+    /// makes failures in a batch loud instead of silently logged.
+    /// The panic hook is still invoked as usual.
+    pub fn propagate_panics(&mut self, enable: bool) {
+        self.shared.propagate_panics.store(enable, Ordering::Relaxed);
+    }
+
+    /// Block until every submitted job has completed
+    ///
+    /// Returns once the job queue is empty and no worker is running
+    /// a job; the pool stays usable afterwards. Delayed jobs that
+    /// are not yet due are not waited for. With `propagate_panics`
+    /// enabled, the first job panic since the last call is returned
+    /// as an error.
+    pub fn wait_idle(&self) -> Result<(), JobPanic> {
+        let guard = self.shared.idle_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let _guard = self.shared.idle
+            .wait_while(guard, |_| !self.shared.is_idle())
+            .unwrap_or_else(|e| e.into_inner());
+        match self.shared.first_panic.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Block until every submitted job has completed or the timeout
+    /// expires; returns whether the pool became idle
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.shared.idle_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let (_guard, res) = self.shared.idle
+            .wait_timeout_while(guard, timeout, |_| !self.shared.is_idle())
+            .unwrap_or_else(|e| e.into_inner());
+        !res.timed_out()
+    }
+
+    /// Execute work once the given delay has elapsed
+    ///
+    /// Delayed jobs are held by a single timer thread, started on
+    /// first use, which forwards them to the job queue when due.
+    /// Jobs still waiting on their delay when the pool shuts down
+    /// are dropped without running.
+    pub fn execute_after<F>(&mut self, delay: Duration, work: F)
+        where F: FnOnce() + Send + 'static
+    {
+        let at = Instant::now() + delay;
+        self.timer().schedule(at, Timed::Once(Box::new(work)));
+    }
+
+    /// Execute work every `period` until the returned handle is
+    /// cancelled
+    ///
+    /// The first run happens one period from now. Runs never
+    /// overlap: if the previous run is still in progress when the
+    /// next one is due, that tick is skipped. All schedules end when
+    /// the pool shuts down.
+    pub fn schedule_every<F>(&mut self, period: Duration, work: F) -> ScheduleHandle
+        where F: Fn() + Send + Sync + 'static
+    {
+        let periodic = Arc::new(Periodic::new(period, work));
+        let at = Instant::now() + period;
+        self.timer().schedule(at, Timed::Every(Arc::clone(&periodic)));
+        ScheduleHandle::new(periodic)
+    }
+
+    /// Get the timer, starting its thread on first use
+    fn timer(&mut self) -> &Timer<Timed> {
+        if self.timer.is_none() {
+            let sender = self.sender.clone().unwrap();
+            let shared = Arc::clone(&self.shared);
+            let name = format!("{}-timer", self.name_prefix);
+            let timer = Timer::new(name, move |at, timed| {
+                match timed {
+                    Timed::Once(work) => {
+                        let _ = shared.send_job(&sender, work);
+                        None
+                    }
+                    Timed::Every(periodic) => {
+                        if periodic.is_cancelled() {
+                            return None;
+                        }
+                        if let Some(job) = periodic.next_run() {
+                            let _ = shared.send_job(&sender, Box::new(job));
+                        }
+                        // don't try to catch up on missed ticks
+                        let now = Instant::now();
+                        let mut next = at + periodic.period;
+                        if next <= now {
+                            next = now + periodic.period;
+                        }
+                        Some((next, Timed::Every(periodic)))
+                    }
+                }
+            });
+            self.timer = Some(timer.unwrap());
+        }
+        self.timer.as_ref().unwrap()
+    }
+
+    /// Execute work and return a handle to its result
+    ///
+    /// The handle can be joined to block for the value or polled
+    /// with `try_recv`.
+    pub fn execute_with_result<F, T>(&mut self, work: F) -> JobHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        let (job, handle) = job::with_result(work);
+        self.execute(job);
+        handle
+    }
+
+    /// Run work on the pool and wait for its result
+    ///
+    /// Keeps the work off the calling thread, e.g. to isolate a
+    /// panic or to run it with the workers' stack size, while the
+    /// caller blocks as if it ran inline. A panic is returned as an
+    /// error, and so is a pool that no longer accepts jobs. Since this
+    /// waits for the job, like `scope`, the work may borrow from the
+    /// caller; calling it from a job on the same pool can deadlock the
+    /// same way.
+    pub fn execute_blocking<F, R>(&mut self, work: F) -> Result<R, BlockingError>
+        where F: FnOnce() -> R + Send,
+              R: Send
+    {
+        let mut res = None;
+        let queued = self.scope(|s| {
+            let res = &mut res;
+            s.try_execute(move || {
+                *res = Some(panic::catch_unwind(AssertUnwindSafe(work))
+                    .map_err(|e| JobPanic::from_payload(worker_index(), &*e)));
+            })
+        });
+        if queued.is_err() {
+            return Err(BlockingError::Closed);
+        }
+        // the scope only returns once the job has run
+        res.unwrap().map_err(BlockingError::Panicked)
+    }
+
+    /// Hand a stateful closure to a worker and return its trigger
+    ///
+    /// The closure runs on the same worker every time the trigger is
+    /// fired, so state it captures is kept between runs without being
+    /// reallocated. The job occupies one worker until the trigger is
+    /// dropped.
+    pub fn execute_stateful<F>(&mut self, work: F) -> Trigger
+        where F: FnMut() + Send + 'static
+    {
+        let (job, trigger) = job::with_trigger(work);
+        self.execute(job);
+        trigger
+    }
+
+    /// Map a function over the inputs in parallel
+    ///
+    /// One job is submitted per input and the results are returned
+    /// in input order once every job has finished. If any job
+    /// panics, the first panic received is returned as an error.
+    pub fn map<T, R, F>(&mut self, inputs: Vec<T>, f: F) -> Result<Vec<R>, JobPanic>
+        where T: Send + 'static,
+              R: Send + 'static,
+              F: Fn(T) -> R + Send + Sync + Clone + 'static
+    {
+        let n = inputs.len();
+        let (tx, rx) = mpsc::channel();
+        for (i, input) in inputs.into_iter().enumerate() {
+            let tx = tx.clone();
+            let f = f.clone();
+            self.execute(move || {
+                let res = panic::catch_unwind(AssertUnwindSafe(|| f(input)))
+                    .map_err(|e| JobPanic::from_payload(worker_index(), &*e));
+                let _ = tx.send((i, res));
+            });
+        }
+        drop(tx);
+
+        // results arrive in completion order; slot them by index
+        let mut results: Vec<Option<R>> = (0..n).map(|_| None).collect();
+        let mut failed = None;
+        for (i, res) in rx.iter() {
+            match res {
+                Ok(r) => results[i] = Some(r),
+                Err(e) => {
+                    failed.get_or_insert(e);
+                }
+            }
+        }
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(results.into_iter().map(Option::unwrap).collect()),
+        }
+    }
+
+    /// Execute work carrying a cancel token and return a handle to
+    /// its result
+    ///
+    /// If the token is cancelled before a worker picks the job up it
+    /// is skipped and the handle reports `JobError::Cancelled`. Once
+    /// running, the job has to poll a clone of the token itself.
+    pub fn execute_with_token<F, T>(&mut self, token: &CancelToken, work: F) -> JobHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        let (job, handle) = job::with_token(token, work);
+        self.execute(job);
+        handle
+    }
+
+    /// Execute work and return a handle whose `join` gives up after
+    /// the timeout
+    ///
+    /// The deadline counts from submission. Once it has passed,
+    /// `join` returns `JobError::Timeout` and a job still queued is
+    /// skipped. Safe Rust cannot abort a running thread, though: a
+    /// job already running keeps its worker until it returns, and
+    /// its value is discarded. Jobs that should actually stop early
+    /// have to poll a `CancelToken`; see `execute_with_token`.
+    pub fn execute_with_timeout<F, T>(&mut self, timeout: Duration, work: F) -> JobHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        let (job, handle) = job::with_timeout(timeout, work);
+        self.execute(job);
+        handle
+    }
+
+    /// Shutdown the pool
+    ///
+    /// Closes the job channel, lets the workers drain all queued
+    /// jobs and joins every worker. Workers that panicked are
+    /// reported by index instead of propagating the panic.
+    pub fn shutdown(mut self) -> Result<(), WorkerError> {
+        self.stop()
+    }
+
+    /// Shutdown the pool, waiting at most `timeout` for workers
+    ///
+    /// Like `shutdown`, but workers still busy when the timeout
+    /// expires are handed back in the error instead of being waited
+    /// for. Threads cannot be killed, so this only reclaims workers
+    /// whose jobs are cooperative and return in time; the caller can
+    /// join the stragglers later, leak them by dropping the error or
+    /// abort the process. Worker panics are only logged.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Result<(), StillRunning> {
+        let deadline = Instant::now() + timeout;
+        self.close();
+        loop {
+            self.reap();
+            if self.pool.iter().all(Option::is_none) || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        if !self.panicked.is_empty() {
+            eprintln!("Workers::shutdown_timeout {}", WorkerError::Panicked(self.panicked.clone()));
+        }
+        let handles: Vec<_> = self.pool.iter_mut()
+            .enumerate()
+            .filter_map(|(idx, w)| w.take().map(|w| (idx, w)))
+            .collect();
+        if handles.is_empty() {
+            Ok(())
+        } else {
+            Err(StillRunning::new(handles))
+        }
+    }
+
+    /// Stop the timer and close the job channel
+    fn close(&mut self) {
+        // a paused pool could never drain its queue
+        self.shared.resume();
+        // Stop the timer first, it holds a sender of its own
+        if let Some(mut timer) = self.timer.take() {
+            let dropped = timer.stop();
+            if dropped > 0 {
+                eprintln!("Workers::stop dropped {} delayed jobs", dropped);
+            }
+        }
         // Close the channel
         drop(self.sender.take());
+    }
+
+    fn stop(&mut self) -> Result<(), WorkerError> {
+        self.close();
 
         // wait for all threads to exit
-        for w in &mut self.pool {
-            w.take().unwrap().join().unwrap();
+        let mut panicked = std::mem::take(&mut self.panicked);
+        for (idx, w) in self.pool.iter_mut().enumerate() {
+            if let Some(w) = w.take() {
+                if w.join().is_err() {
+                    panicked.push(idx);
+                }
+            }
+        }
+        panicked.sort_unstable();
+        if panicked.is_empty() {
+            Ok(())
+        } else {
+            Err(WorkerError::Panicked(panicked))
+        }
+    }
+}
+
+/// Graceful shutdown and cleanup
+impl Drop for Workers {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            eprintln!("Workers::drop {}", e);
         }
     }
 }
@@ -133,4 +1140,633 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn test_shutdown() {
+        let mut w = Workers::new(3);
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..20 {
+            let count = Arc::clone(&count);
+            w.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(count.load(Ordering::SeqCst), 20);
+
+        // a panicking hook is not isolated and kills the worker
+        let mut w = Workers::new(1);
+        w.on_panic(|_, _| panic!("bad hook"));
+        w.execute(|| panic!("bad job"));
+        assert_eq!(w.shutdown(), Err(WorkerError::Panicked(vec![0])));
+    }
+
+    #[test]
+    fn test_panic_isolation() {
+        let mut w = Workers::new(1);
+        let caught = Arc::new(Mutex::new(Vec::new()));
+        let list = Arc::clone(&caught);
+        w.on_panic(move |idx, e| {
+            let msg = e.downcast_ref::<&str>().unwrap().to_string();
+            list.lock().unwrap().push((idx, msg));
+        });
+        w.execute(|| panic!("bad job"));
+        // the single worker must survive to run this one
+        let h = w.execute_with_result(|| 42);
+        assert_eq!(h.join(), Ok(42));
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(*caught.lock().unwrap(), vec![(0, "bad job".to_string())]);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut w = Workers::new(2);
+        let count = Arc::new(AtomicUsize::new(0));
+        let submit = |w: &mut Workers| {
+            for _ in 0..10 {
+                let count = Arc::clone(&count);
+                w.execute(move || {
+                    thread::sleep(std::time::Duration::from_millis(1));
+                    count.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        };
+        submit(&mut w);
+        w.resize(5).unwrap();
+        assert_eq!(w.size(), 5);
+        submit(&mut w);
+        w.resize(1).unwrap();
+        assert_eq!(w.size(), 1);
+        // the remaining worker still drains everything queued
+        submit(&mut w);
+        let h = w.execute_with_result(|| thread::current().id());
+        assert!(h.join().is_ok());
+        w.resize(0).unwrap();
+        w.resize(3).unwrap();
+        submit(&mut w);
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(count.load(Ordering::SeqCst), 40);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut w = Workers::with_capacity(1, 1);
+        let (tx, rx) = mpsc::channel::<()>();
+        // park the only worker, then fill the single queue slot
+        w.execute(move || { rx.recv().unwrap(); });
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(w.try_execute(|| ()).is_ok());
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&count);
+        let work = match w.try_execute(move || { c.fetch_add(1, Ordering::SeqCst); }) {
+            Err(TrySubmitError::Full(work)) => work,
+            _ => panic!("queue should be full"),
+        };
+        // the closure comes back intact
+        work();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        tx.send(()).unwrap();
+        assert_eq!(w.shutdown(), Ok(()));
+    }
+
+    #[test]
+    fn test_thread_names() {
+        let mut w = Workers::with_name_prefix(2, "pool").unwrap();
+        let name = w.execute_with_result(|| thread::current().name().map(String::from));
+        let name = name.join().unwrap().unwrap();
+        assert!(name == "pool-0" || name == "pool-1");
+
+        let mut w = Workers::try_new(1).unwrap();
+        let name = w.execute_with_result(|| thread::current().name().map(String::from));
+        assert_eq!(name.join(), Ok(Some("worker-0".to_string())));
+    }
+
+    #[test]
+    fn test_spawn_failure() {
+        // no address space can fit a stack this large
+        let huge = 1usize << 50;
+        let res = Workers::builder().size(3).stack_size(huge).build();
+        assert!(res.is_err());
+
+        // a pool that fails to grow keeps the workers it had
+        let mut w = Workers::try_new(2).unwrap();
+        w.stack_size = Some(huge);
+        assert!(w.resize(4).is_err());
+        assert_eq!(w.live_workers(), 2);
+        let res = w.execute_with_result(|| 42);
+        assert_eq!(res.join(), Ok(42));
+
+        // the workers started before the failure are torn down
+        let exited = Arc::new(Mutex::new(Vec::new()));
+        let e = Arc::clone(&exited);
+        FAIL_SPAWN.with(|f| f.set(Some(2)));
+        let res = Workers::builder()
+            .size(4)
+            .on_worker_exit(move |idx| e.lock().unwrap().push(idx))
+            .build();
+        FAIL_SPAWN.with(|f| f.set(None));
+        assert!(res.is_err());
+        // the hook is only released with the pool state, once every
+        // started worker, and its live count, is gone
+        assert_eq!(Arc::strong_count(&exited), 1);
+        let mut exited = exited.lock().unwrap().clone();
+        exited.sort_unstable();
+        assert_eq!(exited, [0, 1]);
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut w = Workers::new(2);
+        let (tx, rx) = mpsc::channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..5 {
+            let rx = Arc::clone(&rx);
+            w.execute(move || { rx.lock().unwrap().recv().unwrap(); });
+        }
+        thread::sleep(std::time::Duration::from_millis(50));
+        // one job holds the lock, the other worker is waiting on it
+        assert_eq!(w.active(), 2);
+        assert_eq!(w.pending(), 3);
+        for _ in 0..5 {
+            tx.send(()).unwrap();
+        }
+        assert_eq!(w.execute_with_result(|| ()).join(), Ok(()));
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(w.active(), 0);
+        assert_eq!(w.pending(), 0);
+    }
+
+    #[test]
+    fn test_execute_after() {
+        let mut w = Workers::new(2);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+        for (n, ms) in [(3, 60), (1, 20), (2, 40)] {
+            let order = Arc::clone(&order);
+            w.execute_after(Duration::from_millis(ms), move || {
+                order.lock().unwrap().push((n, start.elapsed()));
+            });
+        }
+        // never due before shutdown; dropped without running
+        let order2 = Arc::clone(&order);
+        w.execute_after(Duration::from_secs(60), move || {
+            order2.lock().unwrap().push((4, start.elapsed()));
+        });
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(w.shutdown(), Ok(()));
+        let order = order.lock().unwrap();
+        assert_eq!(order.iter().map(|o| o.0).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(order[0].1 >= Duration::from_millis(20));
+        assert!(order[2].1 >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_wait_idle() {
+        let mut w = Workers::new(3);
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            for _ in 0..12 {
+                let count = Arc::clone(&count);
+                w.execute(move || {
+                    thread::sleep(Duration::from_millis(5));
+                    count.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            w.wait_idle().unwrap();
+            assert_eq!(w.pending() + w.active(), 0);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 24);
+
+        w.execute(|| thread::sleep(Duration::from_millis(200)));
+        assert!(!w.wait_idle_timeout(Duration::from_millis(10)));
+        assert!(w.wait_idle_timeout(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_map() {
+        let mut w = Workers::new(4);
+        let squares = w.map((0..20).collect(), |i: u64| {
+            // finish out of order
+            thread::sleep(Duration::from_millis(20 - i));
+            i * i
+        });
+        assert_eq!(squares, Ok((0..20).map(|i| i * i).collect()));
+
+        let res = w.map(vec![1, 2, 3], |i: i32| {
+            if i == 2 {
+                panic!("bad input {}", i);
+            }
+            i
+        });
+        let err = res.unwrap_err();
+        assert!(err.worker < 4);
+        assert_eq!(err.message, Some("bad input 2".to_string()));
+    }
+
+    #[test]
+    fn test_execute_blocking() {
+        let mut w = Workers::new(2);
+        let data = [1, 2, 3];
+        // the work borrows from the caller's stack
+        let caller = thread::current().id();
+        let res = w.execute_blocking(|| {
+            assert_ne!(thread::current().id(), caller);
+            data.iter().sum::<i32>()
+        });
+        assert_eq!(res, Ok(6));
+
+        let err = w.execute_blocking(|| -> i32 { panic!("bad job") }).unwrap_err();
+        assert!(matches!(err, BlockingError::Panicked(p) if p.message.as_deref() == Some("bad job")));
+        // the pool keeps working
+        assert_eq!(w.execute_blocking(|| data.len()), Ok(3));
+
+        // a closed pool fails the call instead of hanging
+        w.close_submission();
+        assert_eq!(w.execute_blocking(|| data.len()), Err(BlockingError::Closed));
+    }
+
+    #[test]
+    fn test_try_execute_if_idle() {
+        let w = Workers::new(2);
+        let (tx, rx) = mpsc::channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+        // occupy both workers
+        for _ in 0..2 {
+            let rx = Arc::clone(&rx);
+            w.try_execute_if_idle(move || {
+                let _ = rx.lock().unwrap().recv();
+            }).unwrap();
+        }
+        let res = w.try_execute_if_idle(|| ());
+        assert!(matches!(res, Err(Busy(_))));
+        // the work is handed back to run inline
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&ran);
+        if let Err(Busy(work)) = w.try_execute_if_idle(move || flag.store(true, Ordering::SeqCst)) {
+            work();
+        }
+        assert!(ran.load(Ordering::SeqCst));
+
+        drop(tx);
+        w.wait_idle().unwrap();
+        assert!(w.try_execute_if_idle(|| ()).is_ok());
+        w.wait_idle().unwrap();
+        w.pause();
+        assert!(w.try_execute_if_idle(|| ()).is_err());
+        w.resume();
+
+        // a worker leaving a shrinking pool is not idle
+        let (exit_tx, exit_rx) = mpsc::channel();
+        let (release, hold) = mpsc::channel::<()>();
+        let hold = Mutex::new(hold);
+        let mut w = Workers::builder()
+            .size(2)
+            .on_worker_exit(move |idx| {
+                exit_tx.send(idx).unwrap();
+                let _ = hold.lock().unwrap().recv();
+            })
+            .build()
+            .unwrap();
+        w.resize(1).unwrap();
+        exit_rx.recv().unwrap();
+        let (tx, rx) = mpsc::channel::<()>();
+        w.try_execute_if_idle(move || {
+            let _ = rx.recv();
+        }).unwrap();
+        assert!(matches!(w.try_execute_if_idle(|| ()), Err(Busy(_))));
+        drop(tx);
+        drop(release);
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let mut w = Workers::new(2);
+        w.execute(|| thread::sleep(Duration::from_millis(10)));
+        assert!(w.shutdown_timeout(Duration::from_secs(5)).is_ok());
+
+        let mut w = Workers::new(2);
+        let (tx, rx) = mpsc::channel::<()>();
+        let (started_tx, started) = mpsc::channel();
+        // a wedged job holding one worker
+        w.execute(move || {
+            started_tx.send(()).unwrap();
+            rx.recv().unwrap();
+        });
+        started.recv().unwrap();
+        let err = w.shutdown_timeout(Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.workers().len(), 1);
+        // release the job and reclaim the straggler
+        tx.send(()).unwrap();
+        assert_eq!(err.join(), Ok(()));
+    }
+
+    #[test]
+    fn test_handle() {
+        let w = Workers::new(2);
+        let count = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..3).map(|_| {
+            let h = w.handle();
+            let count = Arc::clone(&count);
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    let count = Arc::clone(&count);
+                    h.execute(move || { count.fetch_add(1, Ordering::SeqCst); });
+                }
+            })
+        }).collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(count.load(Ordering::SeqCst), 30);
+    }
+
+    #[test]
+    fn test_submit() {
+        let mut w = Workers::new(1);
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&count);
+        assert!(w.submit(move || { c.fetch_add(1, Ordering::SeqCst); }).is_ok());
+        w.wait_idle().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // once closed the job comes back instead of panicking
+        w.close();
+        let c = Arc::clone(&count);
+        match w.submit(move || { c.fetch_add(1, Ordering::SeqCst); }) {
+            Err(SubmitError(work)) => work(),
+            Ok(_) => panic!("pool should be closed"),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_schedule_every() {
+        let mut w = Workers::new(2);
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&count);
+        let fast = w.schedule_every(Duration::from_millis(10), move || {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        // a slow job skips the ticks that would overlap with it
+        let inflight = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(AtomicUsize::new(0));
+        let (i, o) = (Arc::clone(&inflight), Arc::clone(&overlapped));
+        w.schedule_every(Duration::from_millis(10), move || {
+            if i.fetch_add(1, Ordering::SeqCst) > 0 {
+                o.fetch_add(1, Ordering::SeqCst);
+            }
+            thread::sleep(Duration::from_millis(50));
+            i.fetch_sub(1, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(150));
+        fast.cancel();
+        assert!(fast.is_cancelled());
+        thread::sleep(Duration::from_millis(20));
+        let runs = count.load(Ordering::SeqCst);
+        assert!(runs >= 3);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(count.load(Ordering::SeqCst), runs);
+        // shutdown ends the remaining schedule
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(overlapped.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_work_stealing() {
+        let mut w = Workers::new_work_stealing(4);
+        let count = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..4).map(|_| {
+            let h = w.handle();
+            let count = Arc::clone(&count);
+            thread::spawn(move || {
+                for _ in 0..250 {
+                    let count = Arc::clone(&count);
+                    h.execute(move || { count.fetch_add(1, Ordering::SeqCst); });
+                }
+            })
+        }).collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        w.wait_idle().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1000);
+        // the rest of the api works the same
+        assert_eq!(w.execute_with_result(|| 6 * 7).join(), Ok(42));
+        w.resize(2).unwrap();
+        w.resize(6).unwrap();
+        assert_eq!(w.map(vec![1, 2, 3], |i: i32| i + 1), Ok(vec![2, 3, 4]));
+        assert_eq!(w.shutdown(), Ok(()));
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut w = Workers::new(2);
+        let count = Arc::new(AtomicUsize::new(0));
+        w.pause();
+        assert!(w.is_paused());
+        for _ in 0..10 {
+            let count = Arc::clone(&count);
+            w.execute(move || { count.fetch_add(1, Ordering::SeqCst); });
+        }
+        thread::sleep(Duration::from_millis(50));
+        // nothing ran, nothing was lost
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert_eq!(w.pending(), 10);
+        assert!(!w.wait_idle_timeout(Duration::from_millis(10)));
+
+        w.resume();
+        assert!(!w.is_paused());
+        w.wait_idle().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 10);
+
+        // shutting down a paused pool still drains it
+        w.pause();
+        let c = Arc::clone(&count);
+        w.execute(move || { c.fetch_add(1, Ordering::SeqCst); });
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(count.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn test_propagate_panics() {
+        let mut w = Workers::new(2);
+        w.on_panic(|_, _| ());
+        // off by default
+        w.execute(|| panic!("ignored"));
+        assert_eq!(w.wait_idle(), Ok(()));
+
+        w.propagate_panics(true);
+        w.execute(|| panic!("first"));
+        w.wait_idle_timeout(Duration::from_secs(5));
+        w.execute(|| panic!("{}", "second".to_string()));
+        let err = w.wait_idle().unwrap_err();
+        assert!(err.worker < 2);
+        assert_eq!(err.message, Some("first".to_string()));
+        // reported once, the pool carries on
+        assert_eq!(w.wait_idle(), Ok(()));
+        w.execute(|| panic!("{}", "third".to_string()));
+        assert_eq!(w.wait_idle().unwrap_err().message, Some("third".to_string()));
+    }
+
+    #[test]
+    fn test_poisoned_receiver() {
+        // idle workers block on the receiver lock, so poison it
+        // before any worker is started
+        let mut w = Workers::new(0);
+        if let JobReceiver::Shared(rx) = w.receiver.clone() {
+            let _ = thread::spawn(move || {
+                let _guard = rx.lock().unwrap();
+                panic!("poison");
+            }).join();
+        }
+        w.resize(2).unwrap();
+        w.execute(|| panic!("bad job"));
+        let results: Vec<_> = (0..4).map(|n| w.execute_with_result(move || n * 2)).collect();
+        let results: Vec<_> = results.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![0, 2, 4, 6]);
+        assert_eq!(w.shutdown(), Ok(()));
+    }
+
+    #[test]
+    fn test_healthy() {
+        let mut w = Workers::new(2);
+        assert!(w.healthy());
+        assert_eq!(w.live_workers(), 2);
+        w.resize(0).unwrap();
+        let start = Instant::now();
+        while w.healthy() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!w.healthy());
+        assert_eq!(w.live_workers(), 0);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut w = Workers::with_rate_limit(2, 20);
+        let start = Instant::now();
+        // a full bucket bursts 20 jobs, the other 10 take ~0.5s
+        for _ in 0..30 {
+            w.execute(|| {});
+        }
+        w.wait_idle().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        w.set_rate(0);
+        let start = Instant::now();
+        for _ in 0..100 {
+            w.execute(|| {});
+        }
+        w.wait_idle().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_close_submission() {
+        let mut w = Workers::new(1);
+        let handle = w.handle();
+        let (tx, rx) = mpsc::channel();
+        let done = Arc::new(AtomicUsize::new(0));
+        w.execute(move || { rx.recv().unwrap(); });
+        for _ in 0..3 {
+            let done = Arc::clone(&done);
+            w.execute(move || { done.fetch_add(1, Ordering::SeqCst); });
+        }
+        w.close_submission();
+        assert!(w.submit(|| {}).is_err());
+        assert!(matches!(w.try_execute(|| {}), Err(TrySubmitError::Closed(_))));
+        assert!(handle.submit(|| {}).is_err());
+        drop(handle);
+        // queued jobs still run
+        tx.send(()).unwrap();
+        assert_eq!(w.shutdown(), Ok(()));
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut w = Workers::new(2);
+        assert_eq!(w.throughput_per_sec(), 0.0);
+        for _ in 0..10 {
+            w.execute(|| {});
+        }
+        w.execute(|| panic!("bad job"));
+        w.wait_idle().unwrap();
+        assert_eq!(w.completed(), 10);
+        assert_eq!(w.panicked(), 1);
+        assert!(w.throughput_per_sec() > 0.0);
+    }
+
+    #[test]
+    fn test_builder() {
+        let caught = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&caught);
+        let mut w = Workers::builder()
+            .size(3)
+            .queue_capacity(2)
+            .name_prefix("built")
+            .stack_size(128 * 1024)
+            .on_panic(move |_, _| { c.fetch_add(1, Ordering::SeqCst); })
+            .build()
+            .unwrap();
+        assert_eq!(w.size(), 3);
+        let name = w.execute_with_result(|| thread::current().name().unwrap().to_string());
+        assert!(name.join().unwrap().starts_with("built-"));
+        w.execute(|| panic!("bad job"));
+        w.wait_idle().unwrap();
+        assert_eq!(caught.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_on_worker_exit() {
+        let exited = Arc::new(Mutex::new(Vec::new()));
+        let e = Arc::clone(&exited);
+        let mut w = Workers::builder()
+            .size(3)
+            .on_worker_exit(move |idx| e.lock().unwrap().push(idx))
+            .build()
+            .unwrap();
+        w.execute(|| ());
+        w.wait_idle().unwrap();
+        assert!(exited.lock().unwrap().is_empty());
+        drop(w);
+        let mut exited = exited.lock().unwrap().clone();
+        exited.sort_unstable();
+        assert_eq!(exited, [0, 1, 2]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_affinity() {
+        let mut w = Workers::with_affinity(2, &[0]).unwrap();
+        let pinned = w.execute_with_result(|| unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set);
+            (0..libc::CPU_SETSIZE as usize).filter(|&c| libc::CPU_ISSET(c, &set)).collect::<Vec<_>>()
+        });
+        assert_eq!(pinned.join(), Ok(vec![0]));
+    }
+
+    #[test]
+    fn test_affinity_out_of_range() {
+        // a core past the cpu set leaves the workers unpinned
+        let mut w = Workers::with_affinity(2, &[4096]).unwrap();
+        let (started_tx, started) = mpsc::channel();
+        let (gate_tx, gate) = mpsc::channel::<()>();
+        let gate = Arc::new(Mutex::new(gate));
+        for _ in 0..2 {
+            let started_tx = started_tx.clone();
+            let gate = Arc::clone(&gate);
+            w.execute(move || {
+                started_tx.send(()).unwrap();
+                let _ = gate.lock().unwrap().recv();
+            });
+        }
+        for _ in 0..2 {
+            started.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert_eq!(w.live_workers(), 2);
+        drop(gate_tx);
+    }
 }
\ No newline at end of file
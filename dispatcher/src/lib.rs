@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Something events can be pushed into
+///
+/// Implemented by `EventHandler` and `HandlerSender` of the
+/// eventhandler crate and by `EventManager` and `Publisher` of the
+/// eventmanager crate, so code can take either a single handler or a
+/// set of subscribers as its event sink.
+pub trait Dispatcher<T> {
+    /// Queue an event for handling
+    ///
+    /// The event is handed back if it can no longer be handled.
+    fn dispatch(&self, event: T) -> Result<(), DispatchError<T>>;
+}
+
+/// Error returned by `Dispatcher::dispatch` when the event can no
+/// longer be handled; the event is handed back
+pub struct DispatchError<T>(pub T);
+
+impl<T> fmt::Debug for DispatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DispatchError(..)")
+    }
+}
+
+impl<T> fmt::Display for DispatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dispatching on a closed channel")
+    }
+}
+
+impl<T> std::error::Error for DispatchError<T> {}
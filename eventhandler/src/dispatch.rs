@@ -0,0 +1,38 @@
+use crate::eventmgr::{EventHandler, HandlerSender};
+
+/// The event sink trait shared with the eventmanager crate
+pub use dispatcher::{DispatchError, Dispatcher};
+
+impl<T: Sync + Send + 'static> Dispatcher<T> for EventHandler<T> {
+    fn dispatch(&self, event: T) -> Result<(), DispatchError<T>> {
+        self.send(event).map_err(|e| DispatchError(e.0))
+    }
+}
+
+impl<T> Dispatcher<T> for HandlerSender<T> {
+    fn dispatch(&self, event: T) -> Result<(), DispatchError<T>> {
+        self.send(event).map_err(|e| DispatchError(e.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn feed(sink: &dyn Dispatcher<u32>, events: &[u32]) {
+        for e in events {
+            sink.dispatch(*e).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dispatcher() {
+        let (tx, rx) = mpsc::channel();
+        let ev_mgr = EventHandler::new(move |event: u32| tx.send(event).unwrap());
+        feed(&ev_mgr, &[1, 2]);
+        feed(&ev_mgr.sender(), &[3]);
+        drop(ev_mgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+}
@@ -1,5 +1,13 @@
+use std::any::Any;
 use std::thread;
-use std::sync::mpsc::{self, Sender, Receiver};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::fmt;
+use std::mem;
+use std::sync::mpsc::{self, Sender, SyncSender, Receiver, RecvError, RecvTimeoutError, SendError, TrySendError};
+use std::time::{Duration, Instant};
+use crate::ring::{self, RingSender, RingReceiver};
 
 /// Generic Event Handler
 ///
@@ -11,7 +19,7 @@ use std::sync::mpsc::{self, Sender, Receiver};
 /// #[derive(Debug)]
 /// enum Event {
 ///     String(&'static str),
-///     Bytes(&'static [u8]),
+///     Bytes(Vec<u8>),
 ///     Empty
 /// }
 ///
@@ -29,14 +37,281 @@ use std::sync::mpsc::{self, Sender, Receiver};
 ///     }
 /// });
 ///
-/// ev_mgr.send(Event::String("Hello World"));
-/// ev_mgr.send(Event::Bytes(&[0xAA, 0xBB, 0xCC]));
-/// ev_mgr.send(Event::Empty);
+/// ev_mgr.send(Event::String("Hello World")).unwrap();
+/// // byte payloads can be built at runtime
+/// let bytes: Vec<u8> = (0xAA..=0xCC).step_by(0x11).collect();
+/// ev_mgr.send(Event::Bytes(bytes)).unwrap();
+/// ev_mgr.send(Event::Empty).unwrap();
 /// ```
 ///
 pub struct EventHandler<T> {
     thread: Option<thread::JoinHandle<()>>,
-    sender: Option<Sender<T>>
+    sender: Option<EventSender<T>>,
+    alive: Arc<AtomicBool>,
+    handler: Arc<Mutex<Handler<T>>>,
+    pending: Arc<AtomicUsize>
+}
+
+/// Handler function of an event handler
+enum Handler<T> {
+    Each(Box<dyn Fn(T) + Send + 'static>),
+    EachMut(Box<dyn FnMut(T) + Send + 'static>),
+    Batch(Box<dyn Fn(Vec<T>) + Send + 'static>)
+}
+
+/// How many events the handler thread collects before calling the
+/// handler, and for how long
+#[derive(Clone, Copy)]
+struct Batching {
+    max_batch: usize,
+    max_delay: Duration
+}
+
+/// Every event is handled as soon as it is received
+const UNBATCHED: Batching = Batching { max_batch: 1, max_delay: Duration::ZERO };
+
+/// Error returned by `EventHandler::send_timeout`, handing the event
+/// back
+pub enum SendTimeoutError<T> {
+    /// The channel stayed full until the timeout expired
+    Timeout(T),
+    /// The handler thread is gone
+    Disconnected(T),
+}
+
+impl<T> fmt::Debug for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "Timeout(..)"),
+            SendTimeoutError::Disconnected(_) => write!(f, "Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "timed out waiting on a full channel"),
+            SendTimeoutError::Disconnected(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendTimeoutError<T> {}
+
+/// The handler thread panicked; returned by `EventHandler::shutdown`
+///
+/// Panics of the handler itself are caught, so this takes a panic
+/// elsewhere on the thread, e.g. in the `new_resilient` hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerPanic {
+    /// The panic message, if the payload was a string
+    pub message: Option<String>,
+}
+
+impl HandlerPanic {
+    fn from_payload(payload: &(dyn Any + Send)) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            Some(s.to_string())
+        } else {
+            payload.downcast_ref::<String>().cloned()
+        };
+        HandlerPanic { message }
+    }
+}
+
+impl fmt::Display for HandlerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(m) => write!(f, "event handler thread panicked: {}", m),
+            None => write!(f, "event handler thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for HandlerPanic {}
+
+type PanicHook = Box<dyn Fn(Box<dyn Any + Send>) + Send + 'static>;
+
+/// Clears the alive flag when the handler thread exits, even when
+/// it unwinds
+struct Alive(Arc<AtomicBool>);
+
+impl Drop for Alive {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// What a lossy event handler drops when its queue is full; see
+/// `EventHandler::new_lossy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Discard the event being sent
+    DropNewest,
+    /// Discard the oldest queued event to make room
+    DropOldest,
+}
+
+/// Sending side of the event channel
+enum EventSender<T> {
+    Unbounded(Sender<T>),
+    Bounded(SyncSender<T>),
+    // drops new events on a full channel, counting them
+    DropNewest(SyncSender<T>, Arc<AtomicU64>),
+    DropOldest(RingSender<T>)
+}
+
+impl<T> EventSender<T> {
+    /// Queue an event, blocking while a bounded channel is full, and
+    /// count it as pending
+    fn send(&self, event: T, pending: &AtomicUsize) -> Result<(), SendError<T>> {
+        let res = match self {
+            EventSender::Unbounded(tx) => {
+                pending.fetch_add(1, Ordering::Relaxed);
+                tx.send(event)
+            }
+            EventSender::Bounded(tx) => {
+                pending.fetch_add(1, Ordering::Relaxed);
+                tx.send(event)
+            }
+            _ => return self.try_send(event, pending).map_err(|e| match e {
+                TrySendError::Full(e) | TrySendError::Disconnected(e) => SendError(e),
+            }),
+        };
+        if res.is_err() {
+            pending.fetch_sub(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    /// Queue an event without blocking and count it as pending
+    fn try_send(&self, event: T, pending: &AtomicUsize) -> Result<(), TrySendError<T>> {
+        // count the event before the handler thread can dequeue it
+        pending.fetch_add(1, Ordering::Relaxed);
+        let res = match self {
+            EventSender::Unbounded(tx) => tx.send(event)
+                .map_err(|e| TrySendError::Disconnected(e.0)),
+            EventSender::Bounded(tx) => tx.try_send(event),
+            EventSender::DropNewest(tx, dropped) => match tx.try_send(event) {
+                Err(TrySendError::Full(_)) => {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    pending.fetch_sub(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                res => res,
+            },
+            EventSender::DropOldest(tx) => match tx.push(event) {
+                Ok(displaced) => {
+                    if displaced {
+                        pending.fetch_sub(1, Ordering::Relaxed);
+                    }
+                    Ok(())
+                }
+                Err(event) => Err(TrySendError::Disconnected(event)),
+            },
+        };
+        if res.is_err() {
+            pending.fetch_sub(1, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            EventSender::Unbounded(tx) => EventSender::Unbounded(tx.clone()),
+            EventSender::Bounded(tx) => EventSender::Bounded(tx.clone()),
+            EventSender::DropNewest(tx, dropped) => {
+                EventSender::DropNewest(tx.clone(), Arc::clone(dropped))
+            }
+            EventSender::DropOldest(tx) => EventSender::DropOldest(tx.clone()),
+        }
+    }
+}
+
+/// Cloneable handle for sending events to an `EventHandler`
+///
+/// Obtained from `EventHandler::sender`; can be moved to other
+/// threads to feed one handler from several places. The handler
+/// thread keeps running until every sender is gone, so dropping the
+/// handler blocks while senders are still alive.
+pub struct HandlerSender<T> {
+    sender: EventSender<T>,
+    pending: Arc<AtomicUsize>
+}
+
+impl<T> Clone for HandlerSender<T> {
+    fn clone(&self) -> Self {
+        HandlerSender { sender: self.sender.clone(), pending: Arc::clone(&self.pending) }
+    }
+}
+
+impl<T> HandlerSender<T> {
+    /// Send event to event handler; see `EventHandler::send`
+    pub fn send(&self, event: T) -> Result<(), SendError<T>> {
+        self.sender.send(event, &self.pending)
+    }
+
+    /// Send event without blocking; see `EventHandler::try_send`
+    pub fn try_send(&self, event: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send(event, &self.pending)
+    }
+}
+
+/// Receiving side of the event channel
+enum EventReceiver<T> {
+    Channel(Receiver<T>),
+    Ring(RingReceiver<T>)
+}
+
+impl<T> EventReceiver<T> {
+    fn recv(&self) -> Result<T, RecvError> {
+        match self {
+            EventReceiver::Channel(rx) => rx.recv(),
+            EventReceiver::Ring(rx) => rx.recv(),
+        }
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match self {
+            EventReceiver::Channel(rx) => rx.recv_timeout(timeout),
+            EventReceiver::Ring(rx) => rx.recv_timeout(timeout),
+        }
+    }
+}
+
+/// Run the current handler for a batch of events
+///
+/// A panicking handler must not take the thread down; the panic is
+/// reported and the next event is handled.
+fn handle<T>(handler: &Mutex<Handler<T>>, on_panic: &Option<PanicHook>, batch: Vec<T>) {
+    let report = |e| match on_panic {
+        Some(hook) => hook(e),
+        None => eprintln!("Event EventHandler: handler panicked"),
+    };
+    match &mut *handler.lock().unwrap_or_else(|e| e.into_inner()) {
+        Handler::Each(h) => {
+            for event in batch {
+                if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| h(event))) {
+                    report(e);
+                }
+            }
+        }
+        Handler::EachMut(h) => {
+            for event in batch {
+                if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| h(event))) {
+                    report(e);
+                }
+            }
+        }
+        Handler::Batch(h) => {
+            if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| h(batch))) {
+                report(e);
+            }
+        }
+    }
 }
 
 impl <T: Sync + Send + 'static>EventHandler<T> {
@@ -47,45 +322,289 @@ impl <T: Sync + Send + 'static>EventHandler<T> {
     {
         // create event channel
         let (tx, rx): (Sender<T>, Receiver<T>) = mpsc::channel();
+        let rx = EventReceiver::Channel(rx);
+        EventHandler::with_channel(EventSender::Unbounded(tx), rx, Handler::Each(Box::new(handler)), None, UNBATCHED)
+    }
 
+    /// Create a new event handler with a stateful handler function
+    ///
+    /// The handler may mutate what it captures, e.g. a counter or a
+    /// growing buffer, without interior mutability of its own: only
+    /// the handler thread ever calls it, one event at a time.
+    pub fn new_mut<F>(handler: F) -> Self
+        where F: FnMut(T) + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let rx = EventReceiver::Channel(rx);
+        EventHandler::with_channel(EventSender::Unbounded(tx), rx, Handler::EachMut(Box::new(handler)), None, UNBATCHED)
+    }
+
+    /// Create a new event handler whose handler can fail
+    ///
+    /// Errors returned by `handler` are passed to `on_error`, which
+    /// also runs on the handler thread, so the handler itself stays
+    /// free of logging and metrics code.
+    pub fn new_fallible<F, E, R>(handler: F, on_error: R) -> Self
+        where F: Fn(T) -> Result<(), E> + Send + 'static,
+              E: Send + 'static,
+              R: Fn(E) + Send + 'static
+    {
+        EventHandler::new(move |event| {
+            if let Err(e) = handler(event) {
+                on_error(e);
+            }
+        })
+    }
+
+    /// Create a new event handler reporting handler panics to a hook
+    ///
+    /// Every event handler survives a panicking handler: the panic
+    /// is caught, the event is dropped and the thread goes on with
+    /// the next event, so `send` keeps working. Here the panic
+    /// payload is passed to `on_panic` on the handler thread instead
+    /// of being logged to stderr.
+    pub fn new_resilient<F, P>(handler: F, on_panic: P) -> Self
+        where F: Fn(T) + Send + 'static,
+              P: Fn(Box<dyn Any + Send>) + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let rx = EventReceiver::Channel(rx);
+        let handler = Handler::Each(Box::new(handler));
+        EventHandler::with_channel(EventSender::Unbounded(tx), rx, handler, Some(Box::new(on_panic)), UNBATCHED)
+    }
+
+    /// Create a new event handler whose event channel holds at most
+    /// `cap` events
+    ///
+    /// Once `cap` events are waiting, `send` blocks until the
+    /// handler catches up and `try_send` hands the event back, so
+    /// memory stays bounded under bursts.
+    pub fn with_capacity<F>(cap: usize, handler: F) -> Self
+        where F: Fn(T) + Send + 'static
+    {
+        let (tx, rx) = mpsc::sync_channel(cap);
+        let rx = EventReceiver::Channel(rx);
+        EventHandler::with_channel(EventSender::Bounded(tx), rx, Handler::Each(Box::new(handler)), None, UNBATCHED)
+    }
+
+    /// Create a new event handler which drops events rather than
+    /// block when `cap` events are waiting
+    ///
+    /// `policy` picks whether the event being sent or the oldest
+    /// queued one is discarded. Sending never blocks then and only
+    /// fails if the handler thread is gone; `dropped_count` tells
+    /// how many events were lost.
+    pub fn new_lossy<F>(cap: usize, policy: DropPolicy, handler: F) -> Self
+        where F: Fn(T) + Send + 'static
+    {
+        let (tx, rx) = match policy {
+            DropPolicy::DropNewest => {
+                let (tx, rx) = mpsc::sync_channel(cap);
+                let tx = EventSender::DropNewest(tx, Arc::new(AtomicU64::new(0)));
+                (tx, EventReceiver::Channel(rx))
+            }
+            DropPolicy::DropOldest => {
+                let (tx, rx) = ring::ring(cap);
+                (EventSender::DropOldest(tx), EventReceiver::Ring(rx))
+            }
+        };
+        EventHandler::with_channel(tx, rx, Handler::Each(Box::new(handler)), None, UNBATCHED)
+    }
+
+    /// Create a new event handler calling its handler with batches
+    /// of events
+    ///
+    /// The handler thread collects events until it has `max_batch`
+    /// of them or `max_delay` has passed since the first one, then
+    /// calls the handler once with the whole batch, e.g. for a bulk
+    /// insert. A partial batch is handled on shutdown as well.
+    pub fn new_batched<F>(max_batch: usize, max_delay: Duration, handler: F) -> Self
+        where F: Fn(Vec<T>) + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let batching = Batching { max_batch: max_batch.max(1), max_delay };
+        let rx = EventReceiver::Channel(rx);
+        EventHandler::with_channel(EventSender::Unbounded(tx), rx, Handler::Batch(Box::new(handler)), None, batching)
+    }
+
+    fn with_channel(tx: EventSender<T>, rx: EventReceiver<T>, handler: Handler<T>,
+        on_panic: Option<PanicHook>, batching: Batching) -> Self
+    {
+        let alive = Arc::new(AtomicBool::new(true));
+        let guard = Alive(Arc::clone(&alive));
+        let handler = Arc::new(Mutex::new(handler));
+        let current = Arc::clone(&handler);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let queued = Arc::clone(&pending);
         // start handler trhead
         let thread = thread::spawn( move || {
+            let _alive = guard;
             println!("Event EventHandler ready..");
+            let mut batch = Vec::new();
+            let mut deadline = Instant::now();
             loop {
-                // wait, read and process events
-                match rx.recv() {
+                // wait, read and process events; a partial batch is
+                // only waited on until its deadline
+                let res = if batch.is_empty() {
+                    rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                } else {
+                    rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                };
+                match res {
                     Ok(event) => {
                         #[cfg(Debug)]
                         println!("Handling event..");
-                        handler(event);
+                        queued.fetch_sub(1, Ordering::Relaxed);
+                        if batch.is_empty() {
+                            deadline = Instant::now() + batching.max_delay;
+                        }
+                        batch.push(event);
+                        if batch.len() >= batching.max_batch {
+                            // use whichever handler is current
+                            handle(&current, &on_panic, mem::take(&mut batch));
+                        }
                     }
-                    Err(e) => {
-                        eprintln!("Event EventHandler exiting.. {}", e);
+                    Err(RecvTimeoutError::Timeout) => handle(&current, &on_panic, mem::take(&mut batch)),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        if !batch.is_empty() {
+                            handle(&current, &on_panic, mem::take(&mut batch));
+                        }
+                        eprintln!("Event EventHandler exiting.. {}", RecvError);
                         break;
                     }
                 }
             }
         });
 
-        EventHandler{ thread: Some(thread), sender: Some(tx) }
+        EventHandler{ thread: Some(thread), sender: Some(tx), alive, handler, pending }
+    }
+
+    /// Replace the handler function
+    ///
+    /// The thread and the queued events are kept. Each event is
+    /// handled by whichever handler is current when it is dequeued,
+    /// so events sent before this call may still be handled by the
+    /// old or the new closure. Blocks while an event is being
+    /// handled. On a batched event handler the new closure is called
+    /// for each event of a batch.
+    pub fn set_handler<F>(&self, handler: F)
+        where F: Fn(T) + Send + 'static
+    {
+        *self.handler.lock().unwrap_or_else(|e| e.into_inner()) = Handler::Each(Box::new(handler));
+    }
+
+    /// Check whether the handler thread is still running
+    ///
+    /// A panicking handler is caught and logged, so this only turns
+    /// false if the thread has really exited; events sent then are
+    /// never handled and a supervisor should recreate the handler.
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
     }
 
     /// Send event to event handler
-    pub fn send(&self, event: T)
+    ///
+    /// Blocks while a bounded channel is full; a lossy one drops an
+    /// event instead. The event is handed back if the handler thread
+    /// is gone.
+    pub fn send(&self, event: T) -> Result<(), SendError<T>>
+    {
+        self.sender.as_ref().unwrap().send(event, &self.pending)
+    }
+
+    /// Send event to event handler, giving up after `timeout` if a
+    /// bounded channel stays full
+    ///
+    /// The event is handed back on timeout, or if the handler thread
+    /// is gone, so the caller can decide to drop or retry it. An
+    /// unbounded channel never times out.
+    pub fn send_timeout(&self, event: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>
+    {
+        let deadline = Instant::now() + timeout;
+        let mut event = event;
+        loop {
+            match self.try_send(event) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(e)) => return Err(SendTimeoutError::Disconnected(e)),
+                Err(TrySendError::Full(e)) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(SendTimeoutError::Timeout(e));
+                    }
+                    // poll until there is room or time is up
+                    thread::sleep((deadline - now).min(Duration::from_millis(1)));
+                    event = e;
+                }
+            }
+        }
+    }
+
+    /// Send event to event handler without blocking
+    ///
+    /// The event is handed back if a bounded channel is full or the
+    /// handler thread is gone.
+    pub fn try_send(&self, event: T) -> Result<(), TrySendError<T>>
     {
-        self.sender.as_ref().unwrap().send(event).unwrap();
+        self.sender.as_ref().unwrap().try_send(event, &self.pending)
+    }
+
+    /// Get a cloneable handle for sending from other threads
+    pub fn sender(&self) -> HandlerSender<T> {
+        HandlerSender {
+            sender: self.sender.as_ref().unwrap().clone(),
+            pending: Arc::clone(&self.pending)
+        }
+    }
+
+    /// Number of events sent but not yet taken up by the handler
+    /// thread
+    ///
+    /// A cheap relaxed load, fine to poll often, e.g. to see how far
+    /// behind the handler is.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Number of events a lossy event handler has dropped; always
+    /// zero for other event handlers
+    pub fn dropped_count(&self) -> u64 {
+        match self.sender.as_ref().unwrap() {
+            EventSender::DropNewest(_, dropped) => dropped.load(Ordering::Relaxed),
+            EventSender::DropOldest(tx) => tx.dropped(),
+            _ => 0,
+        }
+    }
+
+    /// Stop the event handler once every queued event is handled
+    ///
+    /// Closes the channel and waits for the handler thread to drain
+    /// it and exit, like dropping the handler does, but reports a
+    /// panicked thread instead of just logging it. As with dropping,
+    /// this blocks while `HandlerSender`s are still alive.
+    pub fn shutdown(mut self) -> Result<(), HandlerPanic> {
+        self.close()
     }
 
 }
 
+impl<T> EventHandler<T> {
+    /// Close the channel and wait for the handler thread to exit
+    fn close(&mut self) -> Result<(), HandlerPanic> {
+        drop(self.sender.take());
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|e| HandlerPanic::from_payload(&*e)),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Graceful shutdown and cleanup
 impl <T>Drop for EventHandler<T> {
     fn drop(&mut self) {
-        // Close the channel
-        drop(self.sender.take());
-        // wait for handler to exit
-        if let Some(thread) = self.thread.take() {
-            thread.join().unwrap();
+        // a handler thread that panicked must not turn this, maybe
+        // during unwinding, into an abort
+        if let Err(e) = self.close() {
+            eprintln!("EventHandler::drop {}", e);
         }
     }
 }
@@ -94,6 +613,7 @@ impl <T>Drop for EventHandler<T> {
 mod tests {
     use super::*;
 
+    #[allow(clippy::enum_variant_names)]
     enum TestEvent {
         TestString(String),
         TestRaw(&'static [u8]),
@@ -109,8 +629,229 @@ mod tests {
             }
         });
 
-        ev_mgr.send(TestEvent::TestString("Hello World".to_string()));
-        ev_mgr.send(TestEvent::TestRaw(&[1, 2, 3]));
-        ev_mgr.send(TestEvent::TestEmpty);
+        ev_mgr.send(TestEvent::TestString("Hello World".to_string())).unwrap();
+        ev_mgr.send(TestEvent::TestRaw(&[1, 2, 3])).unwrap();
+        ev_mgr.send(TestEvent::TestEmpty).unwrap();
+    }
+
+    #[test]
+    fn test_bounded() {
+        let (tx, rx) = mpsc::channel();
+        let (gate_tx, gate) = mpsc::channel::<()>();
+        let ev_mgr = EventHandler::with_capacity(1, move |event: u32| {
+            tx.send(event).unwrap();
+            let _ = gate.recv();
+        });
+        // park the handler thread inside the first event
+        ev_mgr.send(1).unwrap();
+        assert_eq!(rx.recv(), Ok(1));
+        ev_mgr.try_send(2).unwrap();
+        assert!(matches!(ev_mgr.try_send(3), Err(TrySendError::Full(3))));
+        drop(gate_tx);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_is_alive() {
+        let (tx, rx) = mpsc::channel();
+        let ev_mgr = EventHandler::new(move |event: u32| {
+            if event == 1 {
+                panic!("bad event");
+            }
+            tx.send(event).unwrap();
+        });
+        ev_mgr.send(1).unwrap();
+        ev_mgr.send(2).unwrap();
+        assert_eq!(rx.recv(), Ok(2));
+        assert!(ev_mgr.is_alive());
+
+        // a panicking panic hook really takes the thread down
+        let ev_mgr = EventHandler::new_resilient(|_: u32| panic!("bad event"), |_| panic!("bad hook"));
+        ev_mgr.send(1).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while ev_mgr.is_alive() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!ev_mgr.is_alive());
+        assert!(ev_mgr.shutdown().is_err());
+    }
+
+    #[test]
+    fn test_fallible() {
+        let (tx, rx) = mpsc::channel();
+        let ev_mgr = EventHandler::new_fallible(|event: u32| {
+            if event % 2 == 1 { Err(format!("odd {}", event)) } else { Ok(()) }
+        }, move |e| tx.send(e).unwrap());
+        for event in 0..4 {
+            ev_mgr.send(event).unwrap();
+        }
+        drop(ev_mgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["odd 1", "odd 3"]);
+    }
+
+    #[test]
+    fn test_resilient() {
+        let (tx, rx) = mpsc::channel();
+        let (ptx, prx) = mpsc::channel();
+        let ev_mgr = EventHandler::new_resilient(move |event: u32| {
+            if event == 1 {
+                panic!("bad event");
+            }
+            tx.send(event).unwrap();
+        }, move |e| ptx.send(*e.downcast::<&str>().unwrap()).unwrap());
+        for event in 0..3 {
+            ev_mgr.send(event).unwrap();
+        }
+        drop(ev_mgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(prx.iter().collect::<Vec<_>>(), vec!["bad event"]);
+    }
+
+    #[test]
+    fn test_send_timeout() {
+        let (gate_tx, gate) = mpsc::channel::<()>();
+        let (started_tx, started) = mpsc::channel();
+        let ev_mgr = EventHandler::with_capacity(1, move |_: u32| {
+            let _ = started_tx.send(());
+            let _ = gate.recv();
+        });
+        ev_mgr.send(1).unwrap();
+        started.recv().unwrap();
+        ev_mgr.send_timeout(2, Duration::from_millis(10)).unwrap();
+        let start = Instant::now();
+        let res = ev_mgr.send_timeout(3, Duration::from_millis(50));
+        assert!(matches!(res, Err(SendTimeoutError::Timeout(3))));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        drop(gate_tx);
+    }
+
+    #[test]
+    fn test_set_handler() {
+        let (tx, rx) = mpsc::channel();
+        let tx2 = tx.clone();
+        let ev_mgr = EventHandler::new(move |event: u32| tx.send(("old", event)).unwrap());
+        ev_mgr.send(1).unwrap();
+        assert_eq!(rx.recv(), Ok(("old", 1)));
+        ev_mgr.set_handler(move |event: u32| tx2.send(("new", event)).unwrap());
+        ev_mgr.send(2).unwrap();
+        assert_eq!(rx.recv(), Ok(("new", 2)));
+    }
+
+    #[test]
+    fn test_lossy() {
+        for (policy, kept) in [(DropPolicy::DropNewest, vec![0, 1, 2]), (DropPolicy::DropOldest, vec![0, 3, 4])] {
+            let (tx, rx) = mpsc::channel();
+            let (gate_tx, gate) = mpsc::channel::<()>();
+            let ev_mgr = EventHandler::new_lossy(2, policy, move |event: u32| {
+                tx.send(event).unwrap();
+                let _ = gate.recv();
+            });
+            // park the handler thread inside the first event
+            ev_mgr.send(0).unwrap();
+            assert_eq!(rx.recv(), Ok(0));
+            for event in 1..5 {
+                ev_mgr.send(event).unwrap();
+            }
+            assert_eq!(ev_mgr.dropped_count(), 2);
+            drop(gate_tx);
+            drop(ev_mgr);
+            assert_eq!(std::iter::once(0).chain(rx.iter()).collect::<Vec<_>>(), kept);
+        }
+    }
+
+    #[test]
+    fn test_batched() {
+        let (tx, rx) = mpsc::channel();
+        let ev_mgr = EventHandler::new_batched(3, Duration::from_millis(50), move |batch: Vec<u32>| {
+            tx.send(batch).unwrap();
+        });
+        for event in 0..4 {
+            ev_mgr.send(event).unwrap();
+        }
+        assert_eq!(rx.recv(), Ok(vec![0, 1, 2]));
+        // a partial batch goes out once the delay has passed
+        let start = Instant::now();
+        assert_eq!(rx.recv(), Ok(vec![3]));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // and on shutdown
+        ev_mgr.send(4).unwrap();
+        drop(ev_mgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![vec![4]]);
+    }
+
+    #[test]
+    fn test_pending() {
+        let (gate_tx, gate) = mpsc::channel::<()>();
+        let (started_tx, started) = mpsc::channel();
+        let ev_mgr = EventHandler::new(move |_: u32| {
+            let _ = started_tx.send(());
+            let _ = gate.recv();
+        });
+        ev_mgr.send(1).unwrap();
+        started.recv().unwrap();
+        assert_eq!(ev_mgr.pending(), 0);
+        ev_mgr.send(2).unwrap();
+        ev_mgr.try_send(3).unwrap();
+        assert_eq!(ev_mgr.pending(), 2);
+        gate_tx.send(()).unwrap();
+        started.recv().unwrap();
+        assert_eq!(ev_mgr.pending(), 1);
+        drop(gate_tx);
+    }
+
+    #[test]
+    fn test_sender() {
+        let (tx, rx) = mpsc::channel();
+        let ev_mgr = EventHandler::new(move |ev: u32| tx.send(ev).unwrap());
+        let producers: Vec<_> = (0..3).map(|i| {
+            let sender = ev_mgr.sender();
+            thread::spawn(move || {
+                for j in 0..10 {
+                    sender.send(i * 10 + j).unwrap();
+                }
+            })
+        }).collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        drop(ev_mgr);
+        let mut got: Vec<u32> = rx.iter().collect();
+        got.sort();
+        assert_eq!(got, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_new_mut() {
+        let (tx, rx) = mpsc::channel();
+        let mut total = 0;
+        let ev_mgr = EventHandler::new_mut(move |event: u32| {
+            total += event;
+            tx.send(total).unwrap();
+        });
+        for event in 1..=4 {
+            ev_mgr.send(event).unwrap();
+        }
+        drop(ev_mgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 3, 6, 10]);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let h = Arc::clone(&handled);
+        let ev_mgr = EventHandler::new(move |_: u32| {
+            thread::sleep(Duration::from_millis(10));
+            h.fetch_add(1, Ordering::SeqCst);
+        });
+        for event in 0..5 {
+            ev_mgr.send(event).unwrap();
+        }
+        assert_eq!(ev_mgr.shutdown(), Ok(()));
+        assert_eq!(handled.load(Ordering::SeqCst), 5);
+
+        // a panic escaping to the thread is reported, not an abort
+        let ev_mgr = EventHandler::new_resilient(|_: u32| panic!("bad event"), |_| panic!("bad hook"));
+        ev_mgr.send(1).unwrap();
+        let err = ev_mgr.shutdown().unwrap_err();
+        assert_eq!(err.message.as_deref(), Some("bad hook"));
+    }
+}
@@ -1,9 +1,12 @@
 
+pub mod dispatch;
 pub mod eventmgr;
+mod ring;
+pub use crate::dispatch::*;
 pub use crate::eventmgr::*;
 
 pub enum Event {
     One(String),
-    Two(&'static [u8]),
+    Two(Vec<u8>),
     Three
 }
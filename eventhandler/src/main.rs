@@ -10,7 +10,7 @@ fn main() {
         }
     });
 
-    ev_mgr.send(Event::One("Hello World".to_string()));
-    ev_mgr.send(Event::Two(&[1, 2, 3]));
-    ev_mgr.send(Event::Three);
+    ev_mgr.send(Event::One("Hello World".to_string())).unwrap();
+    ev_mgr.send(Event::Two((1..=3).collect())).unwrap();
+    ev_mgr.send(Event::Three).unwrap();
 }
@@ -0,0 +1,136 @@
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Bounded queue that discards its oldest item to make room
+///
+/// Pushing never blocks: once `cap` items are waiting the front one
+/// is dropped and counted. The queue closes when every sender or
+/// the receiver is gone.
+struct Ring<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    cap: usize,
+    dropped: AtomicU64
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiver: bool
+}
+
+pub(crate) struct RingSender<T> {
+    ring: Arc<Ring<T>>
+}
+
+pub(crate) struct RingReceiver<T> {
+    ring: Arc<Ring<T>>
+}
+
+/// Create a ring holding at most `cap` items, at least one
+pub(crate) fn ring<T>(cap: usize) -> (RingSender<T>, RingReceiver<T>) {
+    let cap = cap.max(1);
+    let ring = Arc::new(Ring {
+        state: Mutex::new(State { queue: VecDeque::with_capacity(cap), senders: 1, receiver: true }),
+        ready: Condvar::new(),
+        cap,
+        dropped: AtomicU64::new(0)
+    });
+    (RingSender { ring: Arc::clone(&ring) }, RingReceiver { ring })
+}
+
+impl<T> Ring<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> RingSender<T> {
+    /// Queue an item, dropping the oldest one if the ring is full;
+    /// returns whether an item was dropped, or hands the item back
+    /// if the receiver is gone
+    pub(crate) fn push(&self, item: T) -> Result<bool, T> {
+        let mut st = self.ring.lock();
+        if !st.receiver {
+            return Err(item);
+        }
+        let full = st.queue.len() == self.ring.cap;
+        if full {
+            st.queue.pop_front();
+            self.ring.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        st.queue.push_back(item);
+        self.ring.ready.notify_one();
+        Ok(full)
+    }
+
+    /// Number of items dropped to make room
+    pub(crate) fn dropped(&self) -> u64 {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for RingSender<T> {
+    fn clone(&self) -> Self {
+        self.ring.lock().senders += 1;
+        RingSender { ring: Arc::clone(&self.ring) }
+    }
+}
+
+impl<T> Drop for RingSender<T> {
+    fn drop(&mut self) {
+        let mut st = self.ring.lock();
+        st.senders -= 1;
+        if st.senders == 0 {
+            self.ring.ready.notify_all();
+        }
+    }
+}
+
+impl<T> RingReceiver<T> {
+    /// Block for the next item; fails once the ring is empty and
+    /// every sender is gone
+    pub(crate) fn recv(&self) -> Result<T, RecvError> {
+        let mut st = self.ring.lock();
+        loop {
+            if let Some(item) = st.queue.pop_front() {
+                return Ok(item);
+            }
+            if st.senders == 0 {
+                return Err(RecvError);
+            }
+            st = self.ring.ready.wait(st).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Block for the next item for at most `timeout`
+    pub(crate) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut st = self.ring.lock();
+        loop {
+            if let Some(item) = st.queue.pop_front() {
+                return Ok(item);
+            }
+            if st.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            st = self.ring.ready.wait_timeout(st, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+}
+
+impl<T> Drop for RingReceiver<T> {
+    fn drop(&mut self) {
+        let mut st = self.ring.lock();
+        st.receiver = false;
+        st.queue.clear();
+    }
+}
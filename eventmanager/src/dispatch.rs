@@ -0,0 +1,59 @@
+use crate::eventmgr::{EventManager, Publisher};
+
+/// The event sink trait shared with the eventhandler crate
+pub use dispatcher::{DispatchError, Dispatcher};
+
+impl<T: Sync + Send + 'static> Dispatcher<T> for EventManager<T> {
+    fn dispatch(&self, event: T) -> Result<(), DispatchError<T>> {
+        self.publish(event).map_err(|e| DispatchError(e.into_inner()))
+    }
+}
+
+impl<T> Dispatcher<T> for Publisher<T> {
+    fn dispatch(&self, event: T) -> Result<(), DispatchError<T>> {
+        self.publish(event).map_err(|e| DispatchError(e.into_inner()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn feed(sink: &dyn Dispatcher<u32>, events: &[u32]) {
+        for e in events {
+            sink.dispatch(*e).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dispatcher() {
+        let (tx, rx) = mpsc::channel();
+        let mut ev_mgr = EventManager::new();
+        ev_mgr.subscribe(move |event: &u32| tx.send(*event).unwrap());
+        feed(&ev_mgr, &[1, 2]);
+        feed(&ev_mgr.publisher(), &[3]);
+        ev_mgr.drain_and_stop();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(ev_mgr.dispatch(4).unwrap_err().0, 4);
+    }
+
+    #[test]
+    fn test_dispatcher_shared() {
+        // one sink type for a handler of either crate
+        let (tx, rx) = mpsc::channel();
+        let handler_tx = tx.clone();
+        let handler = eventhandler::EventHandler::new(move |event: u32| handler_tx.send(event).unwrap());
+        let mut ev_mgr = EventManager::new();
+        ev_mgr.subscribe(move |event: &u32| tx.send(*event).unwrap());
+        let sinks: [&dyn Dispatcher<u32>; 2] = [&handler, &ev_mgr];
+        for (i, sink) in sinks.iter().enumerate() {
+            feed(*sink, &[i as u32]);
+        }
+        drop(handler);
+        ev_mgr.drain_and_stop();
+        let mut got = rx.try_iter().collect::<Vec<_>>();
+        got.sort_unstable();
+        assert_eq!(got, vec![0, 1]);
+    }
+}
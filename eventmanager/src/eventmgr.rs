@@ -1,5 +1,13 @@
+use std::any::Any;
+use std::fmt;
+use std::mem::{self, Discriminant};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use asyncworkers::Workers;
 use std::thread;
-use std::sync::{mpsc, Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, SendError, TrySendError};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
 
 /// Generic Event Handler
 ///
@@ -11,7 +19,7 @@ use std::sync::{mpsc, Arc, Mutex};
 /// #[derive(Debug)]
 /// enum Event {
 ///     String(&'static str),
-///     Bytes(&'static [u8]),
+///     Bytes(Vec<u8>),
 ///     Empty
 /// }
 ///
@@ -25,45 +33,544 @@ use std::sync::{mpsc, Arc, Mutex};
 ///     println!("Subscriber 2: {:?}", e);
 /// });
 ///
-/// ev_mgr.publish(Event::String("Hello World"));
-/// ev_mgr.publish(Event::Bytes(&[0xAA, 0xBB, 0xCC]));
-/// ev_mgr.publish(Event::Empty);
+/// ev_mgr.publish(Event::String("Hello World")).unwrap();
+/// // byte payloads can be built at runtime
+/// let bytes: Vec<u8> = (0xAA..=0xCC).step_by(0x11).collect();
+/// ev_mgr.publish(Event::Bytes(bytes)).unwrap();
+/// ev_mgr.publish(Event::Empty).unwrap();
 /// ```
 ///
-
 pub struct EventManager<T> {
     thread: Option<thread::JoinHandle<()>>,
-    channel: Option<mpsc::Sender<T>>,
-    subscribers: Arc<Mutex<Vec<Subscriber<T>>>>
+    channel: Option<EventSender<T>>,
+    // publishers hold it for reading while they queue an event
+    stopped: Arc<RwLock<bool>>,
+    subscribers: Subscribers<T>,
+    hooks: Arc<Hooks>,
+    next_id: u64
+}
+
+// called with the sequence number and the event; returns whether
+// the event was handled
+type Subscriber<T> = Box<dyn Fn(u64, &T) -> bool + Send + Sync + 'static>;
+type SubscriberList<T> = Mutex<Vec<Arc<Entry<T>>>>;
+type Subscribers<T> = Arc<SubscriberList<T>>;
+type PanicHook = Box<dyn Fn(SubscriptionId, Box<dyn Any + Send>) + Send + Sync + 'static>;
+type SlowHook = Box<dyn Fn(SubscriptionId, Duration) + Send + Sync + 'static>;
+
+/// Which events reach a subscriber
+enum Filter<T> {
+    /// Events the predicate returns true for
+    Predicate(Box<dyn Fn(&T) -> bool + Send + Sync + 'static>),
+    /// Events of one of these enum variants
+    Kinds(Vec<Discriminant<T>>)
+}
+
+impl<T> Filter<T> {
+    fn accepts(&self, event: &T) -> bool {
+        match self {
+            Filter::Predicate(f) => f(event),
+            Filter::Kinds(kinds) => kinds.contains(&mem::discriminant(event)),
+        }
+    }
+}
+
+/// Hooks called on the handler thread
+#[derive(Default)]
+struct Hooks {
+    panic: Mutex<Option<PanicHook>>,
+    slow: Mutex<Option<SlowHook>>,
+    // in nanoseconds; calls taking longer fire the slow hook
+    slow_threshold: AtomicU64
+}
+
+/// Timing of a subscriber's calls, updated by the handler thread
+#[derive(Default)]
+struct Timing {
+    count: AtomicU64,
+    total: AtomicU64,
+    max: AtomicU64
+}
+
+/// Dispatch statistics of a subscriber, see
+/// `EventManager::subscriber_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubStat {
+    pub id: SubscriptionId,
+    /// Number of completed calls, not counting panicked ones
+    pub count: u64,
+    /// Time spent in those calls
+    pub total: Duration,
+    /// Longest single call
+    pub max: Duration
+}
+
+/// A registered subscriber
+struct Entry<T> {
+    id: SubscriptionId,
+    // subscribers with a higher priority are called first
+    priority: i32,
+    // a topic subscriber only gets events published to its topic;
+    // the others get everything
+    topic: Option<String>,
+    // only events passing the filter reach the handler
+    filter: Option<Filter<T>>,
+    // set for a one-shot subscriber once it has fired
+    fired: Option<AtomicBool>,
+    timing: Timing,
+    handler: Subscriber<T>
+}
+
+/// Event queued for the handler thread
+struct Message<T> {
+    event: T,
+    topic: Option<String>,
+    // reports how many subscribers were called, for `publish_counted`
+    ack: Option<mpsc::Sender<usize>>
+}
+
+/// Request to the handler thread
+enum Command<T> {
+    Publish(Message<T>),
+    // acknowledged once everything queued before is handled
+    Flush(mpsc::Sender<()>),
+    // exit once everything queued before is handled
+    Stop
+}
+
+impl<T> Command<T> {
+    /// Get the event back from a publish command that failed to send
+    fn into_event(self) -> T {
+        match self {
+            Command::Publish(msg) => msg.event,
+            Command::Flush(_) | Command::Stop => unreachable!("not a publish command"),
+        }
+    }
 }
 
-type Subscriber<T> = Box<dyn Fn(&T) + Send + Sync + 'static>;
+/// Sending side of the event channel
+enum EventSender<T> {
+    Unbounded(mpsc::Sender<Command<T>>),
+    Bounded(mpsc::SyncSender<Command<T>>)
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            EventSender::Unbounded(tx) => EventSender::Unbounded(tx.clone()),
+            EventSender::Bounded(tx) => EventSender::Bounded(tx.clone()),
+        }
+    }
+}
+
+impl<T> EventSender<T> {
+    /// Queue a command, blocking while a bounded channel is full
+    fn send_command(&self, cmd: Command<T>) -> Result<(), SendError<Command<T>>> {
+        match self {
+            EventSender::Unbounded(tx) => tx.send(cmd),
+            EventSender::Bounded(tx) => tx.send(cmd),
+        }
+    }
+
+    /// Queue a message, blocking while a bounded channel is full
+    fn send(&self, msg: Message<T>) -> Result<(), SendError<T>> {
+        self.send_command(Command::Publish(msg)).map_err(|e| SendError(e.0.into_event()))
+    }
+
+    /// Queue a message unless the manager was stopped, blocking
+    /// while a bounded channel is full
+    fn publish(&self, stopped: &RwLock<bool>, msg: Message<T>) -> Result<(), PublishError<T>> {
+        // hold the flag until the message is queued, so the stop
+        // request is queued behind it
+        let stopped = stopped.read().unwrap_or_else(|e| e.into_inner());
+        if *stopped {
+            return Err(PublishError::Stopped(msg.event));
+        }
+        self.send(msg).map_err(|e| PublishError::closed(*stopped, e.0))
+    }
+
+    /// Queue a message without blocking unless the manager was stopped
+    fn try_publish(&self, stopped: &RwLock<bool>, msg: Message<T>) -> Result<(), PublishError<T>> {
+        let stopped = stopped.read().unwrap_or_else(|e| e.into_inner());
+        if *stopped {
+            return Err(PublishError::Stopped(msg.event));
+        }
+        self.try_send(msg).map_err(|e| match e {
+            TrySendError::Full(event) => PublishError::Full(event),
+            TrySendError::Disconnected(event) => PublishError::closed(*stopped, event),
+        })
+    }
+
+    /// Queue a message without blocking
+    fn try_send(&self, msg: Message<T>) -> Result<(), TrySendError<T>> {
+        let cmd = Command::Publish(msg);
+        match self {
+            EventSender::Unbounded(tx) => tx.send(cmd)
+                .map_err(|e| TrySendError::Disconnected(e.0.into_event())),
+            EventSender::Bounded(tx) => tx.try_send(cmd).map_err(|e| match e {
+                TrySendError::Full(c) => TrySendError::Full(c.into_event()),
+                TrySendError::Disconnected(c) => TrySendError::Disconnected(c.into_event()),
+            }),
+        }
+    }
+}
+
+/// Error returned when an event cannot be published; the event is
+/// handed back
+pub enum PublishError<T> {
+    /// The bounded channel is full; only returned by `try_publish`
+    Full(T),
+    /// `EventManager::drain_and_stop` was called
+    Stopped(T),
+    /// The handler thread is gone without being stopped
+    Disconnected(T),
+}
+
+impl<T> PublishError<T> {
+    /// Error for a channel found closed, telling a stop from a crash
+    fn closed(stopped: bool, event: T) -> Self {
+        if stopped {
+            PublishError::Stopped(event)
+        } else {
+            PublishError::Disconnected(event)
+        }
+    }
+
+    /// Take back the event that could not be published
+    pub fn into_inner(self) -> T {
+        match self {
+            PublishError::Full(e) | PublishError::Stopped(e) | PublishError::Disconnected(e) => e,
+        }
+    }
+}
+
+impl<T> fmt::Debug for PublishError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Full(_) => write!(f, "Full(..)"),
+            PublishError::Stopped(_) => write!(f, "Stopped(..)"),
+            PublishError::Disconnected(_) => write!(f, "Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for PublishError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Full(_) => write!(f, "event channel is full"),
+            PublishError::Stopped(_) => write!(f, "event manager is stopped"),
+            PublishError::Disconnected(_) => write!(f, "event handler thread is gone"),
+        }
+    }
+}
+
+impl<T> std::error::Error for PublishError<T> {}
+
+/// Cloneable handle for publishing events from other threads
+///
+/// Obtained from `EventManager::publisher`; it only allows
+/// publishing. The handler thread keeps running until every
+/// publisher is gone, so dropping the manager blocks while
+/// publishers are still alive.
+pub struct Publisher<T> {
+    channel: EventSender<T>,
+    stopped: Arc<RwLock<bool>>
+}
+
+impl<T> Clone for Publisher<T> {
+    fn clone(&self) -> Self {
+        Publisher { channel: self.channel.clone(), stopped: Arc::clone(&self.stopped) }
+    }
+}
+
+impl<T> Publisher<T> {
+    /// Send event to event manager; see `EventManager::publish`
+    pub fn publish(&self, event: T) -> Result<(), PublishError<T>> {
+        self.channel.publish(&self.stopped, Message { event, topic: None, ack: None })
+    }
+
+    /// Send event without blocking; see `EventManager::try_publish`
+    pub fn try_publish(&self, event: T) -> Result<(), PublishError<T>> {
+        self.channel.try_publish(&self.stopped, Message { event, topic: None, ack: None })
+    }
+}
+
+/// Identifies a subscriber registered with `EventManager::subscribe`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionId(u64);
+
+/// Guard for a subscriber registered with
+/// `EventManager::subscribe_guarded`
+///
+/// Dropping the guard unsubscribes; it only holds a weak reference
+/// to the subscriber list, so it may outlive the event manager.
+#[must_use = "dropping the guard unsubscribes immediately"]
+pub struct Subscription<T> {
+    id: SubscriptionId,
+    subscribers: Weak<SubscriberList<T>>
+}
+
+impl<T> Subscription<T> {
+    /// Id of the guarded subscriber
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// Keep the subscriber registered after the guard is gone
+    ///
+    /// It can still be removed with `EventManager::unsubscribe`.
+    pub fn forget(mut self) -> SubscriptionId {
+        self.subscribers = Weak::new();
+        self.id
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        if let Some(list) = self.subscribers.upgrade() {
+            remove(&list, self.id);
+        }
+    }
+}
+
+/// Remove a subscriber from the list; false if it was not there
+fn remove<T>(list: &SubscriberList<T>, id: SubscriptionId) -> bool {
+    let mut list = list.lock().unwrap();
+    let count = list.len();
+    list.retain(|s| s.id != id);
+    list.len() != count
+}
+
+/// Subscribers due to get a message
+///
+/// The list is snapshotted so subscribers run without the lock held
+/// and can't block (un)subscribing.
+fn snapshot<T>(subs: &SubscriberList<T>, msg: &Message<T>) -> Vec<Arc<Entry<T>>> {
+    match subs.lock() {
+        Ok(list) => list.iter()
+            .filter(|s| s.topic.is_none() || s.topic == msg.topic)
+            .cloned()
+            .collect(),
+        Err(e) => {
+            eprintln!("{}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Discriminants of the variants of some sample events, for
+/// `EventManager::subscribe_kinds`
+pub fn kinds_of<T>(samples: &[T]) -> Vec<Discriminant<T>> {
+    samples.iter().map(mem::discriminant).collect()
+}
+
+/// Deliver an event to a subscriber if its filter accepts it and
+/// return whether it was handled
+///
+/// A panicking subscriber is reported and skipped, so the others
+/// still get the event.
+fn deliver<T>(subs: &SubscriberList<T>, hooks: &Hooks, s: &Entry<T>, seq: u64, event: &T) -> bool {
+    // filters run here, not on the publisher
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        if !s.filter.as_ref().is_none_or(|f| f.accepts(event)) {
+            return None;
+        }
+        // a one-shot subscriber fires once, even if it is still
+        // in a snapshot taken by an earlier event
+        if let Some(fired) = &s.fired {
+            if fired.swap(true, Ordering::SeqCst) {
+                return None;
+            }
+            remove(subs, s.id);
+        }
+        let start = Instant::now();
+        (s.handler)(seq, event).then(|| start.elapsed())
+    }));
+    match res {
+        Ok(Some(took)) => {
+            let nanos = took.as_nanos().min(u64::MAX as u128) as u64;
+            s.timing.count.fetch_add(1, Ordering::Relaxed);
+            s.timing.total.fetch_add(nanos, Ordering::Relaxed);
+            s.timing.max.fetch_max(nanos, Ordering::Relaxed);
+            if nanos > hooks.slow_threshold.load(Ordering::Relaxed) {
+                // a panicking hook is not the subscriber's fault
+                if let Some(hook) = hooks.slow.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+                    if panic::catch_unwind(AssertUnwindSafe(|| hook(s.id, took))).is_err() {
+                        eprintln!("Event Manager: slow subscriber hook panicked");
+                    }
+                }
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            // a panicking hook falls back to logging
+            let reported = match hooks.panic.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+                Some(hook) => panic::catch_unwind(AssertUnwindSafe(|| hook(s.id, e))).is_ok(),
+                None => false,
+            };
+            if !reported {
+                eprintln!("Event Manager: subscriber {:?} panicked", s.id);
+            }
+            false
+        }
+    }
+}
+
+/// Counts the subscribers that handled an event and reports the
+/// count to the publisher once every one of them is done
+struct Ack {
+    count: AtomicUsize,
+    tx: Option<mpsc::Sender<usize>>
+}
+
+impl Ack {
+    fn new(tx: Option<mpsc::Sender<usize>>) -> Self {
+        Ack { count: AtomicUsize::new(0), tx }
+    }
+
+    fn handled(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Ack {
+    fn drop(&mut self) {
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(self.count.load(Ordering::SeqCst));
+        }
+    }
+}
+
+/// Ordering guarantees of an event manager delivering events on a
+/// worker pool; see `EventManager::new_parallel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOrder {
+    /// Events are delivered one after another; the subscribers of an
+    /// event run in parallel and all of them finish before the next
+    /// event is delivered. A slow subscriber holds up later events.
+    InOrder,
+    /// Every subscriber call is a job of its own, so subscribers of
+    /// later events may run before those of earlier ones and a slow
+    /// subscriber holds up nobody. `publish_blocking` then only waits
+    /// for the subscribers of its own event.
+    Unordered,
+}
+
+/// How the handler thread delivers events
+enum Delivery {
+    Serial,
+    Parallel(Box<Workers>, DeliveryOrder)
+}
+
+impl<T: Sync + Send + 'static> Default for EventManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl <T: Sync + Send + 'static>EventManager<T> {
     /// Create a new event manager with handler function
     pub fn new() -> Self {
         // create event channel
-        let (tx, rx): (mpsc::Sender<T>, mpsc::Receiver<T>) = mpsc::channel();
-        let subs: Vec<Subscriber<T>> = Vec::new();
+        let (tx, rx): (mpsc::Sender<Command<T>>, mpsc::Receiver<Command<T>>) = mpsc::channel();
+        EventManager::with_channel(EventSender::Unbounded(tx), rx, Delivery::Serial)
+    }
+
+    /// Create a new event manager delivering events to subscribers
+    /// in parallel on a pool of `workers` threads
+    ///
+    /// Serial fan-out on the single handler thread is a bottleneck
+    /// when many subscribers do I/O. Here the handler thread only
+    /// hands subscriber calls to the pool; `order` picks whether
+    /// events are still delivered one after another or fully in
+    /// parallel, see `DeliveryOrder`. Either way a subscriber may
+    /// run concurrently with other subscribers.
+    pub fn new_parallel(workers: usize, order: DeliveryOrder) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let pool = Workers::new(workers);
+        EventManager::with_channel(EventSender::Unbounded(tx), rx, Delivery::Parallel(Box::new(pool), order))
+    }
+
+    /// Create a new event manager whose event channel holds at most
+    /// `cap` events
+    ///
+    /// Once `cap` events are waiting for the handler thread,
+    /// `publish` blocks until there is room and `try_publish`
+    /// hands the event back, so a burst of events is met with
+    /// backpressure instead of growing memory without bound.
+    pub fn with_capacity(cap: usize) -> Self {
+        let (tx, rx) = mpsc::sync_channel(cap);
+        EventManager::with_channel(EventSender::Bounded(tx), rx, Delivery::Serial)
+    }
+
+    fn with_channel(tx: EventSender<T>, rx: mpsc::Receiver<Command<T>>, mut delivery: Delivery) -> Self {
+        let subs: Vec<Arc<Entry<T>>> = Vec::new();
         let subs = Arc::new(Mutex::new(subs));
         let list = Arc::clone(&subs);
+        let hooks = Arc::new(Hooks { slow_threshold: AtomicU64::new(u64::MAX), ..Hooks::default() });
+        let hook = Arc::clone(&hooks);
         // start handler trhead
         let thread = thread::spawn( move || {
             println!("Event Manager ready..");
+            // events are numbered in the order they are received
+            let mut next_seq: u64 = 0;
             loop {
                 // wait, read and process events
                 match rx.recv() {
-                    Ok(event) => {
+                    Ok(Command::Flush(done)) => {
+                        // jobs of earlier events may still be running
+                        if let Delivery::Parallel(pool, DeliveryOrder::Unordered) = &delivery {
+                            let _ = pool.wait_idle();
+                        }
+                        let _ = done.send(());
+                    }
+                    Ok(Command::Stop) => {
+                        eprintln!("Event Manager stopped");
+                        break;
+                    }
+                    Ok(Command::Publish(mut msg)) => {
                         #[cfg(Debug)]
                         println!("Handling event..");
-                        // lock the list and send event to all handlers
-                        match list.lock() {
-                            Ok(list) => {
-                                for s in list.as_slice().into_iter() {
-                                    s(&event);
+                        let seq = next_seq;
+                        next_seq += 1;
+                        let ack = Ack::new(msg.ack.take());
+                        let targets = snapshot(&list, &msg);
+                        match &mut delivery {
+                            Delivery::Serial => {
+                                for s in targets.iter() {
+                                    if deliver(&list, &hook, s, seq, &msg.event) {
+                                        ack.handled();
+                                    }
+                                }
+                            }
+                            Delivery::Parallel(pool, DeliveryOrder::InOrder) => {
+                                let (list, hook, msg, ack) = (&list, &hook, &msg, &ack);
+                                pool.scope(|sc| {
+                                    for s in targets.iter() {
+                                        sc.execute(move || {
+                                            if deliver(list, hook, s, seq, &msg.event) {
+                                                ack.handled();
+                                            }
+                                        });
+                                    }
+                                });
+                            }
+                            Delivery::Parallel(pool, DeliveryOrder::Unordered) => {
+                                // the last job done with the event drops
+                                // it along with the ack
+                                let msg = Arc::new(msg);
+                                let ack = Arc::new(ack);
+                                for s in targets {
+                                    let (list, hook) = (Arc::clone(&list), Arc::clone(&hook));
+                                    let (msg, ack) = (Arc::clone(&msg), Arc::clone(&ack));
+                                    pool.execute(move || {
+                                        if deliver(&list, &hook, &s, seq, &msg.event) {
+                                            ack.handled();
+                                        }
+                                    });
                                 }
-                            },
-                            Err(e) => eprintln!("{}", e),
+                            }
                         }
                     }
                     Err(e) => {
@@ -74,24 +581,406 @@ impl <T: Sync + Send + 'static>EventManager<T> {
             }
         });
 
-        EventManager{ thread: Some(thread), channel: Some(tx), subscribers: subs }
+        let stopped = Arc::new(RwLock::new(false));
+        EventManager{ thread: Some(thread), channel: Some(tx), stopped, subscribers: subs, hooks, next_id: 0 }
     }
 
     /// Subscribe for events
     ///
     /// Registger event handler with this event manager
-    /// to recieve events
-    pub fn subscribe<F>(&mut self, s: F)
+    /// to recieve events; the returned id can be passed to
+    /// `unsubscribe`
+    pub fn subscribe<F>(&mut self, s: F) -> SubscriptionId
         where F: Fn(&T) + Send + Sync + 'static
     {
-        self.subscribers.lock().unwrap().push(Box::new(s));
+        self.add(0, None, None, false, Box::new(move |_, e: &T| { s(e); true }))
+    }
+
+    /// Subscribe for events ahead of or after other subscribers
+    ///
+    /// Subscribers are called in descending priority order, and in
+    /// subscription order within the same priority; `subscribe` and
+    /// the other variants use priority 0. E.g. a validator can run
+    /// before any logger. On a parallel manager the order only
+    /// decides which subscribers are started first.
+    pub fn subscribe_with_priority<F>(&mut self, priority: i32, s: F) -> SubscriptionId
+        where F: Fn(&T) + Send + Sync + 'static
+    {
+        self.add(priority, None, None, false, Box::new(move |_, e: &T| { s(e); true }))
+    }
+
+    /// Subscribe for events along with their sequence numbers
+    ///
+    /// Every event received by the manager gets the next number of
+    /// a gap free sequence starting at zero, so a subscriber that
+    /// sees a gap knows it missed an event, e.g. because it was
+    /// filtered, published to a topic or the subscriber was added
+    /// late. Events reach each subscriber in sequence order, except
+    /// in `DeliveryOrder::Unordered` mode, which gives up ordering.
+    pub fn subscribe_seq<F>(&mut self, s: F) -> SubscriptionId
+        where F: Fn(u64, &T) + Send + Sync + 'static
+    {
+        self.add(0, None, None, false, Box::new(move |seq, e: &T| { s(seq, e); true }))
+    }
+
+    /// Subscribe a stateful handler for events
+    ///
+    /// The handler may mutate what it captures, e.g. to aggregate
+    /// events, without interior mutability of its own; calls are
+    /// serialized by a lock of its own, which plain `subscribe`
+    /// handlers don't pay for.
+    pub fn subscribe_mut<F>(&mut self, s: F) -> SubscriptionId
+        where F: FnMut(&T) + Send + 'static
+    {
+        let s = Mutex::new(s);
+        self.add(0, None, None, false, Box::new(move |_, e: &T| {
+            // keep serving after the handler panicked
+            (s.lock().unwrap_or_else(|e| e.into_inner()))(e);
+            true
+        }))
+    }
+
+    /// Subscribe a handler taking its own copy of each event
+    ///
+    /// The handler thread clones the event for every such subscriber,
+    /// so the handler can move it on, e.g. into another queue. This
+    /// multiplies the cost of cloning by the number of owned
+    /// subscribers, so use it sparingly; subscribers taking `&T` stay
+    /// zero-copy.
+    pub fn subscribe_owned<F>(&mut self, s: F) -> SubscriptionId
+        where T: Clone,
+              F: Fn(T) + Send + Sync + 'static
+    {
+        self.add(0, None, None, false, Box::new(move |_, e: &T| { s(e.clone()); true }))
+    }
+
+    /// Subscribe a handler on behalf of an object held elsewhere
+    ///
+    /// Only a weak reference to the object is kept, so the
+    /// subscription doesn't keep it alive. Before each call the
+    /// handler thread upgrades it and passes the object to the
+    /// handler; once the object is gone the subscription removes
+    /// itself instead, and the event does not count as handled.
+    pub fn subscribe_weak<C, F>(&mut self, weak: Weak<C>, s: F) -> SubscriptionId
+        where C: Send + Sync + 'static,
+              F: Fn(&C, &T) + Send + Sync + 'static
+    {
+        let id = self.new_id();
+        let list = Arc::downgrade(&self.subscribers);
+        self.insert(id, 0, None, None, false, Box::new(move |_, e: &T| {
+            match weak.upgrade() {
+                Some(c) => {
+                    s(&c, e);
+                    true
+                }
+                // the event is not handled by a dead subscription
+                None => {
+                    if let Some(list) = list.upgrade() {
+                        remove(&list, id);
+                    }
+                    false
+                }
+            }
+        }));
+        id
+    }
+
+    /// Subscribe for the events matching a filter
+    ///
+    /// The filter is evaluated on the handler thread for every
+    /// event; the handler is only called when it returns true.
+    ///
+    /// ```
+    /// use eventmanager::*;
+    ///
+    /// let mut ev_mgr = EventManager::new();
+    /// ev_mgr.subscribe_filtered(|e| matches!(e, Event::One(_)), |e: &Event| {
+    ///     println!("Got one: {:?}", e);
+    /// });
+    /// ev_mgr.publish(Event::Three).unwrap();
+    /// ev_mgr.publish(Event::One("Hello".to_string())).unwrap();
+    /// ```
+    pub fn subscribe_filtered<P, F>(&mut self, filter: P, s: F) -> SubscriptionId
+        where P: Fn(&T) -> bool + Send + Sync + 'static,
+              F: Fn(&T) + Send + Sync + 'static
+    {
+        self.add(0, None, Some(Filter::Predicate(Box::new(filter))), false, Box::new(move |_, e: &T| { s(e); true }))
+    }
+
+    /// Subscribe for the events of some enum variants
+    ///
+    /// Only events whose `std::mem::discriminant` is one of `kinds`
+    /// reach the handler; the check is a plain comparison, cheaper
+    /// than a filter closure for the common "only this variant" case.
+    /// `kinds_of` gets the discriminants from sample events.
+    ///
+    /// ```
+    /// use eventmanager::*;
+    ///
+    /// let mut ev_mgr = EventManager::new();
+    /// let kinds = kinds_of(&[Event::One(String::new()), Event::Three]);
+    /// ev_mgr.subscribe_kinds(&kinds, |e: &Event| {
+    ///     println!("Got one or three: {:?}", e);
+    /// });
+    /// ev_mgr.publish(Event::Two(vec![0xAA])).unwrap();
+    /// ev_mgr.publish(Event::Three).unwrap();
+    /// ```
+    pub fn subscribe_kinds<F>(&mut self, kinds: &[Discriminant<T>], s: F) -> SubscriptionId
+        where F: Fn(&T) + Send + Sync + 'static
+    {
+        self.add(0, None, Some(Filter::Kinds(kinds.to_vec())), false, Box::new(move |_, e: &T| { s(e); true }))
+    }
+
+    /// Subscribe for the events published to a topic
+    ///
+    /// The handler is only called for events sent with
+    /// `publish_topic` and this topic. Subscribers registered with
+    /// `subscribe` keep getting every event, with or without topic.
+    pub fn subscribe_topic<F>(&mut self, topic: &str, s: F) -> SubscriptionId
+        where F: Fn(&T) + Send + Sync + 'static
+    {
+        self.add(0, Some(topic.to_string()), None, false, Box::new(move |_, e: &T| { s(e); true }))
+    }
+
+    /// Subscribe for the next event only
+    ///
+    /// The handler is removed right before it is called, so it runs
+    /// exactly once.
+    pub fn subscribe_once<F>(&mut self, s: F) -> SubscriptionId
+        where F: FnOnce(&T) + Send + 'static
+    {
+        let s = Mutex::new(Some(s));
+        self.add(0, None, None, true, Box::new(move |_, e: &T| {
+            match s.lock().unwrap().take() {
+                Some(s) => {
+                    s(e);
+                    true
+                }
+                None => false,
+            }
+        }))
+    }
+
+    fn add(&mut self, priority: i32, topic: Option<String>, filter: Option<Filter<T>>, once: bool,
+        handler: Subscriber<T>) -> SubscriptionId
+    {
+        let id = self.new_id();
+        self.insert(id, priority, topic, filter, once, handler);
+        id
+    }
+
+    /// Allocate the id of a new subscriber
+    fn new_id(&mut self) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Register a subscriber under an id from `new_id`
+    fn insert(&mut self, id: SubscriptionId, priority: i32, topic: Option<String>, filter: Option<Filter<T>>,
+        once: bool, handler: Subscriber<T>)
+    {
+        let fired = once.then(|| AtomicBool::new(false));
+        let entry = Entry { id, priority, topic, filter, fired, timing: Timing::default(), handler };
+        // keep the list sorted by descending priority, after the
+        // subscribers of the same priority
+        let mut list = self.subscribers.lock().unwrap();
+        let at = list.partition_point(|s| s.priority >= priority);
+        list.insert(at, Arc::new(entry));
+    }
+
+    /// Remove a subscriber
+    ///
+    /// Events published afterwards are no longer delivered to it,
+    /// though an event being dispatched right now may still reach
+    /// it once. Returns false if no subscriber with this id is registered.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        remove(&self.subscribers, id)
+    }
+
+    /// Number of registered subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Ids of the registered subscribers, in the order they are
+    /// called
+    pub fn subscription_ids(&self) -> Vec<SubscriptionId> {
+        self.subscribers.lock().unwrap().iter().map(|s| s.id).collect()
+    }
+
+    /// Subscribe for events until the returned guard is dropped
+    pub fn subscribe_guarded<F>(&mut self, s: F) -> Subscription<T>
+        where F: Fn(&T) + Send + Sync + 'static
+    {
+        let id = self.subscribe(s);
+        Subscription { id, subscribers: Arc::downgrade(&self.subscribers) }
+    }
+
+    /// Set a hook called on the handler thread with the id and the
+    /// panic payload whenever a subscriber panics
+    ///
+    /// The panicking subscriber stays registered and the event is
+    /// still delivered to the other subscribers. Without a hook, or
+    /// if the hook panics itself, the panic is logged to stderr.
+    pub fn on_subscriber_panic<F>(&mut self, hook: F)
+        where F: Fn(SubscriptionId, Box<dyn Any + Send>) + Send + Sync + 'static
+    {
+        *self.hooks.panic.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(hook));
+    }
+
+    /// Set a hook called on the handler thread with the id and the
+    /// duration whenever a single subscriber call takes longer than
+    /// `threshold`
+    ///
+    /// The hook runs after the slow call returned, on the thread that
+    /// made it, so it should be quick itself, e.g. log a warning.
+    pub fn on_slow_subscriber<F>(&mut self, threshold: Duration, hook: F)
+        where F: Fn(SubscriptionId, Duration) + Send + Sync + 'static
+    {
+        *self.hooks.slow.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(hook));
+        let nanos = threshold.as_nanos().min(u64::MAX as u128) as u64;
+        self.hooks.slow_threshold.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Call count and timing of every registered subscriber, in the
+    /// order they are called
+    ///
+    /// Every subscriber call is timed on the handler thread, or on
+    /// the pool of a parallel manager, to find the subscriber hogging
+    /// it. Calls in progress are not included yet.
+    pub fn subscriber_stats(&self) -> Vec<SubStat> {
+        self.subscribers.lock().unwrap().iter().map(|s| SubStat {
+            id: s.id,
+            count: s.timing.count.load(Ordering::Relaxed),
+            total: Duration::from_nanos(s.timing.total.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(s.timing.max.load(Ordering::Relaxed)),
+        }).collect()
     }
 
     /// Send event to event manager
-    pub fn publish(&self, event: T) {
-        self.channel.as_ref().unwrap().send(event).unwrap();
+    ///
+    /// Blocks while a bounded channel is full. The event is handed
+    /// back if the manager was stopped or the handler thread is gone.
+    pub fn publish(&self, event: T) -> Result<(), PublishError<T>> {
+        self.channel.as_ref().unwrap().publish(&self.stopped, Message { event, topic: None, ack: None })
+    }
+
+    /// Send event to the subscribers of a topic
+    ///
+    /// Reaches the handlers registered with `subscribe_topic` for
+    /// this topic, plus all subscribers without a topic.
+    pub fn publish_topic(&self, topic: &str, event: T) -> Result<(), PublishError<T>> {
+        let msg = Message { event, topic: Some(topic.to_string()), ack: None };
+        self.channel.as_ref().unwrap().publish(&self.stopped, msg)
+    }
+
+    /// Send event to event manager without blocking
+    ///
+    /// The event is handed back if a bounded channel is full, the
+    /// manager was stopped or the handler thread is gone, so the
+    /// caller can retry or shed it.
+    pub fn try_publish(&self, event: T) -> Result<(), PublishError<T>> {
+        self.channel.as_ref().unwrap().try_publish(&self.stopped, Message { event, topic: None, ack: None })
+    }
+
+    /// Block until every event published before has been handled
+    ///
+    /// Unlike dropping the manager this keeps the channel open, so
+    /// publishing can go on afterwards. Events published by other
+    /// threads while flushing may or may not be waited for.
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        if self.channel.as_ref().unwrap().send_command(Command::Flush(done)).is_ok() {
+            // fails only if the handler thread is gone
+            let _ = flushed.recv();
+        }
+    }
+
+    /// Get a cloneable handle for publishing from other threads
+    pub fn publisher(&self) -> Publisher<T> {
+        Publisher { channel: self.channel.clone().unwrap(), stopped: Arc::clone(&self.stopped) }
+    }
+
+    /// Send event to event manager and wait for it to be handled
+    ///
+    /// Returns how many subscribers handled the event, not counting
+    /// ones that panicked; zero usually means a subscriber was never
+    /// wired up. Like `publish_blocking` this waits for the handler
+    /// thread, so it adds that latency to the caller.
+    pub fn publish_counted(&self, event: T) -> usize {
+        self.send_acked(event).unwrap_or(0)
+    }
+
+    /// Send event to event manager and wait until every subscriber
+    /// has been called for it
+    ///
+    /// Unlike `publish` this blocks until the handler thread has
+    /// worked through every event queued before this one and then
+    /// run all subscribers for this event.
+    ///
+    /// A subscriber must never publish synchronously to the manager
+    /// it is subscribed to: it runs on the handler thread, which
+    /// would then wait for itself and deadlock.
+    pub fn publish_blocking(&self, event: T) -> Result<(), PublishError<T>> {
+        self.send_acked(event).map(|_| ())
+    }
+
+    /// Queue an event and wait for the handler thread to report how
+    /// many subscribers handled it
+    fn send_acked(&self, event: T) -> Result<usize, PublishError<T>> {
+        let (ack, count) = mpsc::channel();
+        self.channel.as_ref().unwrap().publish(&self.stopped, Message { event, topic: None, ack: Some(ack) })?;
+        // the handler thread only drops the ack if it is torn down
+        // while running the subscribers
+        Ok(count.recv().unwrap_or(0))
+    }
+
+}
+
+impl <T>EventManager<T> {
+    /// Handle every event queued so far, then stop the handler thread
+    ///
+    /// The manager stays around, so `subscriber_stats` and the like
+    /// can still be read afterwards, but it no longer dispatches:
+    /// `publish` and its variants, on the manager and its
+    /// `Publisher`s, hand the event back with `PublishError::Stopped`,
+    /// and `flush` returns right away. Unlike dropping the manager,
+    /// this does not wait for the `Publisher`s to be dropped. An event
+    /// published by another thread while stopping is either handled
+    /// or handed back.
+    pub fn drain_and_stop(&mut self) {
+        {
+            // waits for the publishes in flight, which are then
+            // queued ahead of the stop request
+            let mut stopped = self.stopped.write().unwrap_or_else(|e| e.into_inner());
+            if *stopped {
+                return;
+            }
+            *stopped = true;
+        }
+        if let Some(channel) = self.channel.as_ref() {
+            // a failed send means the handler thread is gone already
+            let _ = channel.send_command(Command::Stop);
+        }
+        self.join();
     }
 
+    /// Check whether `drain_and_stop` was called
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for the handler thread to exit once the channel is closed
+    fn join(&mut self) {
+        // a handler thread that panicked must not turn this, maybe
+        // during unwinding, into an abort
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                eprintln!("EventManager: handler thread panicked");
+            }
+        }
+    }
 }
 
 /// Graceful shutdown and cleanup
@@ -100,9 +989,7 @@ impl <T>Drop for EventManager<T> {
         // Close the channel
         drop(self.channel.take());
         // wait for handler to exit
-        if let Some(thread) = self.thread.take() {
-            thread.join().unwrap();
-        }
+        self.join();
     }
 }
 
@@ -110,6 +997,8 @@ impl <T>Drop for EventManager<T> {
 mod tests {
     use super::*;
 
+    // fields are only read through Debug
+    #[allow(dead_code, clippy::enum_variant_names)]
     #[derive(Debug)]
     enum TestEvent {
         TestString(String),
@@ -132,8 +1021,441 @@ mod tests {
             println!("Subscriber 3: {:?}", e);
         });
 
-        evmgr.publish(TestEvent::TestString("Hello World".to_string()));
-        evmgr.publish(TestEvent::TestRaw(&[1, 2, 3]));
-        evmgr.publish(TestEvent::TestEmpty);
+        evmgr.publish(TestEvent::TestString("Hello World".to_string())).unwrap();
+        evmgr.publish(TestEvent::TestRaw(&[1, 2, 3])).unwrap();
+        evmgr.publish(TestEvent::TestEmpty).unwrap();
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let mut evmgr = EventManager::new();
+        let (tx1, rx1) = mpsc::channel();
+        let (tx2, rx2) = mpsc::channel();
+        let id1 = evmgr.subscribe(move |e: &u32| tx1.send(*e).unwrap());
+        evmgr.subscribe(move |e: &u32| tx2.send(*e).unwrap());
+
+        evmgr.publish(1).unwrap();
+        assert_eq!(rx1.recv(), Ok(1));
+        assert_eq!(rx2.recv(), Ok(1));
+
+        assert!(evmgr.unsubscribe(id1));
+        assert!(!evmgr.unsubscribe(id1));
+        evmgr.publish(2).unwrap();
+        assert_eq!(rx2.recv(), Ok(2));
+        drop(evmgr);
+        // the removed subscriber was dropped and got nothing more
+        assert!(rx1.recv().is_err());
+    }
+
+    #[test]
+    fn test_subscription_guard() {
+        let mut evmgr = EventManager::new();
+        let (tx1, rx1) = mpsc::channel();
+        let (tx2, rx2) = mpsc::channel();
+        let guard = evmgr.subscribe_guarded(move |e: &u32| tx1.send(*e).unwrap());
+        let kept = evmgr.subscribe_guarded(move |e: &u32| tx2.send(*e).unwrap());
+        let kept = kept.forget();
+
+        evmgr.publish(1).unwrap();
+        assert_eq!(rx1.recv(), Ok(1));
+        drop(guard);
+        evmgr.publish(2).unwrap();
+        assert_eq!(rx2.recv(), Ok(1));
+        assert_eq!(rx2.recv(), Ok(2));
+        assert_eq!(evmgr.subscription_ids(), vec![kept]);
+        // a forgotten subscription can still be removed by id
+        assert!(evmgr.unsubscribe(kept));
+        assert_eq!(evmgr.subscriber_count(), 0);
+        drop(evmgr);
+        assert!(rx1.recv().is_err());
+        assert!(rx2.recv().is_err());
+    }
+
+    #[test]
+    fn test_publish_counted() {
+        let mut evmgr = EventManager::new();
+        assert_eq!(evmgr.publish_counted(1u32), 0);
+        let id = evmgr.subscribe(|_: &u32| {});
+        evmgr.subscribe(|_: &u32| {});
+        assert_eq!(evmgr.publish_counted(2), 2);
+        evmgr.unsubscribe(id);
+        assert_eq!(evmgr.publish_counted(3), 1);
+    }
+
+    #[test]
+    fn test_subscribe_filtered() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let publisher = thread::current().id();
+        evmgr.subscribe_filtered(move |e: &u32| {
+            assert_ne!(thread::current().id(), publisher);
+            e.is_multiple_of(2)
+        }, move |e: &u32| tx.send(*e).unwrap());
+        assert_eq!(evmgr.publish_counted(1), 0);
+        assert_eq!(evmgr.publish_counted(2), 1);
+        evmgr.publish(3).unwrap();
+        evmgr.publish(4).unwrap();
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_subscribe_kinds() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let kinds = kinds_of(&[TestEvent::TestString(String::new()), TestEvent::TestEmpty]);
+        evmgr.subscribe_kinds(&kinds, move |e: &TestEvent| tx.send(format!("{:?}", e)).unwrap());
+        assert_eq!(evmgr.publish_counted(TestEvent::TestString("one".to_string())), 1);
+        assert_eq!(evmgr.publish_counted(TestEvent::TestRaw(b"two")), 0);
+        evmgr.publish(TestEvent::TestEmpty).unwrap();
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), ["TestString(\"one\")", "TestEmpty"]);
+    }
+
+    #[test]
+    fn test_slow_subscriber() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.subscribe(move |_: &u32| {
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(500));
+        });
+        evmgr.publish(1).unwrap();
+        rx.recv().unwrap();
+        // the slow subscriber is running; the list must not be locked
+        let start = Instant::now();
+        let id = evmgr.subscribe(|_: &u32| {});
+        evmgr.unsubscribe(id);
+        assert!(start.elapsed() < Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_subscriber_panic() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let (ptx, prx) = mpsc::channel();
+        evmgr.on_subscriber_panic(move |id, _| ptx.send(id).unwrap());
+        let bad = evmgr.subscribe(|e: &u32| if *e == 1 { panic!("bad subscriber") });
+        evmgr.subscribe(move |e: &u32| tx.send(*e).unwrap());
+        // a panicking subscriber is not counted; the other still ran
+        assert_eq!(evmgr.publish_counted(1), 1);
+        assert_eq!(evmgr.publish_counted(2), 2);
+        assert_eq!(prx.try_iter().collect::<Vec<_>>(), vec![bad]);
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut evmgr = EventManager::with_capacity(1);
+        let (tx, rx) = mpsc::channel();
+        let (gate_tx, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        evmgr.subscribe(move |e: &u32| {
+            tx.send(*e).unwrap();
+            let _ = gate.lock().unwrap().recv();
+        });
+        // park the handler thread inside the first event
+        evmgr.publish(1).unwrap();
+        assert_eq!(rx.recv(), Ok(1));
+        evmgr.try_publish(2).unwrap();
+        assert!(matches!(evmgr.try_publish(3), Err(PublishError::Full(3))));
+        drop(gate_tx);
+    }
+
+    #[test]
+    fn test_publish_blocking() {
+        let mut evmgr = EventManager::new();
+        let done = Arc::new(Mutex::new(Vec::new()));
+        let list = Arc::clone(&done);
+        evmgr.subscribe(move |e: &u32| {
+            thread::sleep(Duration::from_millis(50));
+            list.lock().unwrap().push(*e);
+        });
+        evmgr.publish(1).unwrap();
+        evmgr.publish_blocking(2).unwrap();
+        // both the queued event and this one have been handled
+        assert_eq!(*done.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_topics() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let (tx_a, tx_b) = (tx.clone(), tx.clone());
+        evmgr.subscribe_topic("a", move |e: &u32| tx_a.send(("a", *e)).unwrap());
+        evmgr.subscribe_topic("b", move |e: &u32| tx_b.send(("b", *e)).unwrap());
+        evmgr.subscribe(move |e: &u32| tx.send(("all", *e)).unwrap());
+        evmgr.publish_topic("a", 1).unwrap();
+        evmgr.publish_topic("c", 2).unwrap();
+        evmgr.publish(3).unwrap();
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![("a", 1), ("all", 1), ("all", 2), ("all", 3)]);
+    }
+
+    #[test]
+    fn test_subscribe_once() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.subscribe_once(move |e: &u32| tx.send(*e).unwrap());
+        evmgr.subscribe(|_: &u32| {});
+        assert_eq!(evmgr.publish_counted(1), 2);
+        assert_eq!(evmgr.publish_counted(2), 1);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1]);
+        // the handler was dropped along with the subscription
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_parallel() {
+        for order in [DeliveryOrder::InOrder, DeliveryOrder::Unordered] {
+            let mut evmgr = EventManager::new_parallel(4, order);
+            let running = Arc::new(AtomicUsize::new(0));
+            let peak = Arc::new(AtomicUsize::new(0));
+            for _ in 0..4 {
+                let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+                evmgr.subscribe(move |_: &u32| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            let start = Instant::now();
+            assert_eq!(evmgr.publish_counted(1), 4);
+            // the subscribers overlapped rather than running in turn
+            assert!(peak.load(Ordering::SeqCst) > 1);
+            assert!(start.elapsed() < Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn test_publisher() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.subscribe(move |e: &u32| tx.send(*e).unwrap());
+        let threads: Vec<_> = (0..3).map(|i| {
+            let publisher = evmgr.publisher();
+            thread::spawn(move || publisher.publish(i).unwrap())
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        drop(evmgr);
+        let mut got: Vec<_> = rx.iter().collect();
+        got.sort();
+        assert_eq!(got, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_drop_after_panic() {
+        let mut evmgr = EventManager::new();
+        // a panicking hook does not take the handler thread down
+        evmgr.on_subscriber_panic(|_, _| panic!("bad hook"));
+        evmgr.subscribe(|e: &u32| if *e == 1 { panic!("bad subscriber") });
+        evmgr.publish(1).unwrap();
+        assert_eq!(evmgr.publish_counted(2), 1);
+        let (tx, rx) = mpsc::channel();
+        evmgr.on_subscriber_panic(move |id, _| tx.send(id).unwrap());
+        let bad = evmgr.subscribe(|_: &u32| panic!("bad subscriber"));
+        assert_eq!(evmgr.publish_counted(3), 1);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![bad]);
+        drop(evmgr);
+    }
+
+    #[test]
+    fn test_subscribe_seq() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.subscribe_seq(move |seq, e: &u32| tx.send((seq, *e)).unwrap());
+        for e in [10, 20, 30] {
+            evmgr.publish(e).unwrap();
+        }
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![(0, 10), (1, 20), (2, 30)]);
+    }
+
+    #[test]
+    fn test_subscribe_mut() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let mut total = 0;
+        evmgr.subscribe_mut(move |e: &u32| {
+            total += e;
+            tx.send(total).unwrap();
+        });
+        for e in 1..=4 {
+            evmgr.publish(e).unwrap();
+        }
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 3, 6, 10]);
+    }
+
+    #[test]
+    fn test_subscribe_owned() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.subscribe_owned(move |e: String| tx.send(e).unwrap());
+        evmgr.subscribe(|e: &String| assert!(!e.is_empty()));
+        evmgr.publish("one".to_string()).unwrap();
+        evmgr.publish("two".to_string()).unwrap();
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_flush() {
+        for mut evmgr in [EventManager::new(), EventManager::new_parallel(2, DeliveryOrder::Unordered)] {
+            let done = Arc::new(AtomicBool::new(false));
+            let flag = Arc::clone(&done);
+            evmgr.subscribe(move |_: &u32| {
+                thread::sleep(Duration::from_millis(100));
+                flag.store(true, Ordering::SeqCst);
+            });
+            evmgr.publish(1).unwrap();
+            evmgr.flush();
+            assert!(done.load(Ordering::SeqCst));
+            // the manager still takes events after a flush
+            assert_eq!(evmgr.publish_counted(2), 1);
+        }
+    }
+
+    #[test]
+    fn test_subscriber_stats() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        evmgr.on_slow_subscriber(Duration::from_millis(50), move |id, took| tx.send((id, took)).unwrap());
+        let fast = evmgr.subscribe(|_: &u32| {});
+        let slow = evmgr.subscribe(|e: &u32| {
+            if *e == 2 {
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+        for e in 1..=3 {
+            evmgr.publish(e).unwrap();
+        }
+        evmgr.flush();
+
+        let stats = evmgr.subscriber_stats();
+        assert_eq!(stats.iter().map(|s| (s.id, s.count)).collect::<Vec<_>>(), vec![(fast, 3), (slow, 3)]);
+        assert!(stats[0].max < Duration::from_millis(50));
+        assert!(stats[1].max >= Duration::from_millis(100));
+        assert!(stats[1].total >= stats[1].max);
+        // only the slow call was reported
+        let (id, took) = rx.recv().unwrap();
+        assert_eq!(id, slow);
+        assert!(took >= Duration::from_millis(100));
+        drop(evmgr);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_slow_hook_panic() {
+        let mut evmgr = EventManager::new();
+        let (ptx, prx) = mpsc::channel();
+        evmgr.on_subscriber_panic(move |id, _| ptx.send(id).unwrap());
+        evmgr.on_slow_subscriber(Duration::ZERO, |_, _| panic!("bad hook"));
+        evmgr.subscribe(|_: &u32| thread::sleep(Duration::from_millis(1)));
+        // the subscriber is still counted and not reported as panicking
+        assert_eq!(evmgr.publish_counted(1), 1);
+        assert!(prx.try_recv().is_err());
+        let (tx, rx) = mpsc::channel();
+        evmgr.on_slow_subscriber(Duration::ZERO, move |id, _| tx.send(id).unwrap());
+        let id = evmgr.subscribe(|_: &u32| thread::sleep(Duration::from_millis(1)));
+        assert_eq!(evmgr.publish_counted(2), 2);
+        assert!(rx.try_iter().any(|i| i == id));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_subscribe_weak() {
+        struct Component {
+            tx: Mutex<mpsc::Sender<u32>>
+        }
+
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let component = Arc::new(Component { tx: Mutex::new(tx) });
+        let id = evmgr.subscribe_weak(Arc::downgrade(&component), |c: &Component, e: &u32| {
+            c.tx.lock().unwrap().send(*e).unwrap();
+        });
+        assert_eq!(evmgr.publish_counted(1), 1);
+        assert_eq!(rx.try_recv(), Ok(1));
+
+        // a dead subscription does not count as handling the event
+        drop(component);
+        assert_eq!(evmgr.publish_counted(2), 0);
+        assert!(rx.try_recv().is_err());
+        assert!(!evmgr.subscription_ids().contains(&id));
+    }
+
+    #[test]
+    fn test_drain_and_stop() {
+        let mut evmgr = EventManager::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&count);
+        let id = evmgr.subscribe(move |_: &u32| {
+            thread::sleep(Duration::from_millis(10));
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        for e in 0..5 {
+            evmgr.publish(e).unwrap();
+        }
+        // a live publisher does not hold up the stop
+        let publisher = evmgr.publisher();
+        assert!(!evmgr.is_stopped());
+        evmgr.drain_and_stop();
+        assert!(evmgr.is_stopped());
+        assert!(matches!(publisher.publish(7), Err(PublishError::Stopped(7))));
+        // everything queued was handled
+        assert_eq!(count.load(Ordering::SeqCst), 5);
+        assert!(matches!(evmgr.publish(5), Err(PublishError::Stopped(5))));
+        assert!(matches!(evmgr.try_publish(6), Err(PublishError::Stopped(6))));
+        evmgr.flush();
+        // metrics are still there to read
+        assert_eq!(evmgr.subscriber_stats()[0].id, id);
+        assert_eq!(evmgr.subscriber_stats()[0].count, 5);
+    }
+
+    #[test]
+    fn test_drain_and_stop_racing() {
+        // an event accepted while stopping is never dropped
+        for _ in 0..20 {
+            let mut evmgr = EventManager::new();
+            let handled = Arc::new(AtomicUsize::new(0));
+            let counted = Arc::clone(&handled);
+            evmgr.subscribe(move |_: &u32| {
+                counted.fetch_add(1, Ordering::SeqCst);
+            });
+            let publisher = evmgr.publisher();
+            let racer = thread::spawn(move || {
+                let mut accepted = 0;
+                while publisher.publish(1).is_ok() {
+                    accepted += 1;
+                }
+                accepted
+            });
+            thread::sleep(Duration::from_millis(1));
+            evmgr.drain_and_stop();
+            assert_eq!(racer.join().unwrap(), handled.load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn test_subscribe_with_priority() {
+        let mut evmgr = EventManager::new();
+        let (tx, rx) = mpsc::channel();
+        let sub = |name: &'static str| {
+            let tx = tx.clone();
+            move |_: &u32| tx.send(name).unwrap()
+        };
+        evmgr.subscribe(sub("log 1"));
+        evmgr.subscribe_with_priority(-5, sub("audit"));
+        evmgr.subscribe_with_priority(10, sub("validate 1"));
+        evmgr.subscribe(sub("log 2"));
+        evmgr.subscribe_with_priority(10, sub("validate 2"));
+        drop(tx);
+        evmgr.publish(1).unwrap();
+        drop(evmgr);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), ["validate 1", "validate 2", "log 1", "log 2", "audit"]);
+    }
+}
@@ -1,9 +1,26 @@
+pub mod dispatch;
 pub mod eventmgr;
+pub use crate::dispatch::*;
 pub use crate::eventmgr::*;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     One(String),
-    Two(&'static [u8]),
+    Two(Vec<u8>),
     Three
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serde() {
+        for ev in [Event::One("Hello World".to_string()), Event::Two(vec![0xAA, 0xBB, 0xCC]), Event::Three] {
+            let json = serde_json::to_string(&ev).unwrap();
+            assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), ev);
+        }
+        assert_eq!(serde_json::to_string(&Event::Two(vec![1, 2])).unwrap(), r#"{"Two":[1,2]}"#);
+    }
+}
@@ -13,14 +13,14 @@ fn main() {
         println!("Subscriber 2: {:?}", e);
     });
 
-    event_generator(ev_mgr);
+    event_generator(ev_mgr.publisher());
 }
 
-fn event_generator(ev_mgr: EventManager<Event>) {
+fn event_generator(publisher: Publisher<Event>) {
     let t = thread::spawn(move || {
-        ev_mgr.publish(Event::One("Hello World".to_string()));
-        ev_mgr.publish(Event::Two(&[0xAA, 0xBB, 0xCC]));
-        ev_mgr.publish(Event::Three);
+        publisher.publish(Event::One("Hello World".to_string())).unwrap();
+        publisher.publish(Event::Two(vec![0xAA, 0xBB, 0xCC])).unwrap();
+        publisher.publish(Event::Three).unwrap();
     });
 
     let _ = t.join();
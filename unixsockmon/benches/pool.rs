@@ -0,0 +1,50 @@
+
+use unixsockmon::*;
+use std::{fs, thread};
+use std::time::{Duration, Instant};
+
+const MESSAGES: usize = 10_000;
+const ROUNDS: usize = 5;
+
+/// Start a server on the socket and wait for it to come up
+fn server(sock: &'static str, persistent: bool) {
+    if fs::metadata(sock).is_ok() {
+        fs::remove_file(sock).unwrap();
+    }
+    thread::spawn(move || {
+        let mon = SockMonitor::new(sock);
+        let handler = |req: String| Ok(req);
+        if persistent {
+            mon.serve_persistent(SockMonitor::read_line, handler, 2).unwrap();
+        } else {
+            mon.serve_concurrent(SockMonitor::read_line, handler, 2).unwrap();
+        }
+    });
+    while fs::metadata(sock).is_err() {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn bench<F: Fn(&str) -> String>(name: &str, send: F) {
+    let mut best = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        for _ in 0..MESSAGES {
+            assert_eq!(send("ping"), "ping");
+        }
+        best = best.min(start.elapsed());
+    }
+    println!("{:<16} best of {}: {:?} ({:.0} msgs/s)",
+             name, ROUNDS, best, MESSAGES as f64 / best.as_secs_f64());
+}
+
+fn main() {
+    println!("{} messages per round", MESSAGES);
+    server("/tmp/bench-connect.sock", false);
+    let client = SockMonitor::new("/tmp/bench-connect.sock");
+    bench("connect per call", |msg| client.send_string(msg).unwrap());
+
+    server("/tmp/bench-pool.sock", true);
+    let pool = SockClientPool::new("/tmp/bench-pool.sock", 1);
+    bench("pooled", |msg| pool.send(msg).unwrap());
+}
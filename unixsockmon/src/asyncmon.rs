@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use crate::error::{MonitorError, Phase};
+use crate::sockmonitor::{error_reply, SockMonitor, DEFAULT_MAX_LINE_LEN, DEFAULT_MAX_MESSAGE_LEN};
+
+/// Future returned by the readers of an `AsyncSockMonitor`
+pub type ReadFuture<'a> = Pin<Box<dyn Future<Output = Result<String, std::io::Error>> + Send + 'a>>;
+
+/// Unix Named Socket Monitor running on a tokio runtime
+///
+/// The async counterpart of `SockMonitor::serve`: connections are
+/// accepted on the runtime of the caller and each one is served by a
+/// task of its own, so the monitor shares the service's runtime
+/// instead of owning threads. Create it from a configured
+/// `SockMonitor` to use its socket mode, backlog and error hook.
+///
+/// ```
+/// use unixsockmon::AsyncSockMonitor;
+///
+/// let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+/// rt.block_on(async {
+///     let mon = AsyncSockMonitor::new("/tmp/mon_ex4.sock");
+///     let server = mon.serve(AsyncSockMonitor::read_line, |req| async move {
+///         Ok(format!("OK {}", req))
+///     });
+///     // serving stops when the future is dropped
+///     let _ = tokio::time::timeout(std::time::Duration::from_millis(10), server).await;
+/// });
+/// ```
+///
+pub struct AsyncSockMonitor {
+    mon: SockMonitor
+}
+
+impl From<SockMonitor> for AsyncSockMonitor {
+    fn from(mon: SockMonitor) -> Self {
+        AsyncSockMonitor { mon }
+    }
+}
+
+impl AsyncSockMonitor {
+    /// Create a new async named socket monitor
+    pub fn new(sock: &str) -> Self {
+        AsyncSockMonitor { mon: SockMonitor::new(sock) }
+    }
+
+    /// Read a newline terminated string; return string has the
+    /// newline stripped
+    ///
+    /// As `SockMonitor::read_line`, including the
+    /// `DEFAULT_MAX_LINE_LEN` limit.
+    pub fn read_line(stream: &mut UnixStream) -> ReadFuture<'_> {
+        Box::pin(async move {
+            let mut reader = BufReader::new(stream);
+            let mut msg = Vec::new();
+
+            // never buffer more than the limit plus the newline
+            let limit = DEFAULT_MAX_LINE_LEN as u64 + 1;
+            if (&mut reader).take(limit).read_until(b'\n', &mut msg).await? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if msg.ends_with(b"\n") {
+                msg.pop();
+            } else if msg.len() > DEFAULT_MAX_LINE_LEN {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                    format!("line exceeds {} bytes", DEFAULT_MAX_LINE_LEN)));
+            }
+            String::from_utf8(msg).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    /// Read a byte array and return as string
+    ///
+    /// As `SockMonitor::read_bytes`, including the
+    /// `DEFAULT_MAX_MESSAGE_LEN` limit.
+    pub fn read_bytes(stream: &mut UnixStream) -> ReadFuture<'_> {
+        Box::pin(async move {
+            let len = stream.read_u32().await? as usize;
+            if len > DEFAULT_MAX_MESSAGE_LEN {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                    format!("message length {} exceeds {}", len, DEFAULT_MAX_MESSAGE_LEN)));
+            }
+            let mut buffer = vec![0; len];
+            stream.read_exact(&mut buffer).await?;
+            String::from_utf8(buffer).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    /// Serve the named socket
+    ///
+    /// Every connection is handled by a task spawned on the current
+    /// runtime, which reads one message with `reader`, awaits the
+    /// handler and writes its response, or `ERR_PREFIX` and the
+    /// error if the handler failed, before closing the connection.
+    /// Accept errors, e.g. `EMFILE`, are passed to the error hook and
+    /// backed off from as `SockMonitor::set_accept_backoff` sets.
+    /// Serving goes on until the returned future is dropped, which
+    /// removes the socket file, while connections being served
+    /// finish on their own.
+    pub async fn serve<R, H, F>(&self, reader: R, handler: H) -> Result<(), std::io::Error>
+        where R: for<'a> Fn(&'a mut UnixStream) -> ReadFuture<'a>,
+              R: Send + Sync + 'static,
+              H: Fn(String) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<String, Box<dyn Error>>> + Send + 'static
+    {
+        let (listener, _file) = self.mon.bind()?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::UnixListener::from_std(listener)?;
+        let reader = Arc::new(reader);
+        let handler = Arc::new(handler);
+        let on_error = self.mon.error_hook();
+        let mut backoff = self.mon.accept_backoff();
+
+        loop {
+            let mut s = match listener.accept().await {
+                Ok((s, _)) => {
+                    backoff.reset();
+                    s
+                }
+                Err(e) => {
+                    on_error(MonitorError::new(Phase::Accept, e));
+                    tokio::time::sleep(backoff.next()).await;
+                    continue;
+                }
+            };
+            let (reader, handler, on_error) = (Arc::clone(&reader), Arc::clone(&handler), Arc::clone(&on_error));
+            tokio::spawn(async move {
+                let msg = match reader(&mut s).await {
+                    Ok(m) => m,
+                    Err(e) => {
+                        on_error(MonitorError::new(Phase::Read, e));
+                        return;
+                    }
+                };
+                let resp = match handler(msg).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let resp = error_reply(&*e);
+                        on_error(MonitorError::new(Phase::Handle, e));
+                        resp
+                    }
+                };
+                if let Err(e) = s.write_all(resp.as_bytes()).await {
+                    on_error(MonitorError::new(Phase::Write, e));
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread, time};
+
+    #[test]
+    fn test_async_mon() {
+        if fs::metadata("/tmp/mon-async.sock").is_ok() {
+            fs::remove_file("/tmp/mon-async.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let server = thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            rt.block_on(async {
+                let mon = AsyncSockMonitor::new("/tmp/mon-async.sock");
+                let serving = mon.serve(AsyncSockMonitor::read_line, |req| async move {
+                    match req.as_str() {
+                        "fail" => Err("bad request".into()),
+                        _ => Ok(format!("OK {}", req)),
+                    }
+                });
+                // stop serving on request
+                let stop = tokio::task::spawn_blocking(move || rx.recv());
+                tokio::select! {
+                    res = serving => res.unwrap(),
+                    _ = stop => (),
+                }
+            });
+        });
+
+        while fs::metadata("/tmp/mon-async.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(100));
+        }
+        let client = SockMonitor::new("/tmp/mon-async.sock");
+        assert_eq!(client.send_string("hello").unwrap(), "OK hello");
+        assert_eq!(client.send_string("fail").unwrap(), "ERR: bad request");
+        tx.send(()).unwrap();
+        server.join().unwrap();
+        // dropping the serving future removed the socket file
+        assert!(fs::metadata("/tmp/mon-async.sock").is_err());
+    }
+}
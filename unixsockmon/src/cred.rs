@@ -0,0 +1,40 @@
+use std::os::unix::net::UnixStream;
+
+/// Credentials of the process on the other end of a Unix socket
+///
+/// Taken when the peer connected, with `SO_PEERCRED`; only supported
+/// on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32
+}
+
+impl PeerCred {
+    /// Get the credentials of the peer of a connected stream
+    #[cfg(target_os = "linux")]
+    pub fn of(stream: &UnixStream) -> Result<PeerCred, std::io::Error> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: cred and len are valid for writes and len holds the
+        // size of cred
+        let ret = unsafe {
+            libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void, &mut len)
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(PeerCred { pid: cred.pid, uid: cred.uid, gid: cred.gid })
+    }
+
+    /// Get the credentials of the peer of a connected stream; always
+    /// fails with `Unsupported` on this platform
+    #[cfg(not(target_os = "linux"))]
+    pub fn of(_stream: &UnixStream) -> Result<PeerCred, std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Where serving a connection failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Accepting or setting up a connection, or removing the socket
+    /// file when serving stops
+    Accept,
+    /// Reading the message with the reader
+    Read,
+    /// The handler returned an error
+    Handle,
+    /// Writing the response
+    Write,
+    /// A persistent connection was closed for sending no request
+    /// within the idle timeout
+    Idle,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phase::Accept => write!(f, "accept"),
+            Phase::Read => write!(f, "read"),
+            Phase::Handle => write!(f, "handle"),
+            Phase::Write => write!(f, "write"),
+            Phase::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Error reported to the `SockMonitor::on_error` hook while serving
+///
+/// Such errors only affect a single connection; the monitor goes on
+/// serving the others.
+#[derive(Debug)]
+pub struct MonitorError {
+    phase: Phase,
+    error: Box<dyn Error>
+}
+
+impl MonitorError {
+    pub(crate) fn new<E>(phase: Phase, error: E) -> Self
+        where E: Into<Box<dyn Error>>
+    {
+        MonitorError { phase, error: error.into() }
+    }
+
+    /// Where serving the connection failed
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The underlying I/O or handler error
+    pub fn error(&self) -> &(dyn Error + 'static) {
+        &*self.error
+    }
+
+    /// Take the underlying I/O or handler error
+    pub fn into_error(self) -> Box<dyn Error> {
+        self.error
+    }
+}
+
+impl fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.phase, self.error)
+    }
+}
+
+impl Error for MonitorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+/// Error a server answered a request with
+///
+/// The message is the one of the handler error, as sent back in an
+/// `ERR: <message>` response or an `ERR_FRAME`; see
+/// `SockMonitor::parse_reply` and `SockMonitor::parse_binary_reply`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerError {
+    message: String
+}
+
+impl ServerError {
+    pub(crate) fn new(message: &str) -> Self {
+        ServerError { message: message.to_string() }
+    }
+
+    /// The message of the handler error
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "server error: {}", self.message)
+    }
+}
+
+impl Error for ServerError {}
+
+/// Hook receiving the errors of a serving monitor
+pub(crate) type ErrorHook = Arc<dyn Fn(MonitorError) + Send + Sync>;
+
+/// Hook logging errors to stderr, used when none is set
+pub(crate) fn log_error() -> ErrorHook {
+    Arc::new(|e| eprintln!("Monitor::serve:{}", e))
+}
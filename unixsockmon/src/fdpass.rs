@@ -0,0 +1,110 @@
+use std::io::{Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::ptr;
+
+/// Most file descriptors taken from one message; any beyond the
+/// first are closed right away
+const MAX_FDS: usize = 4;
+
+/// Send a byte array, with its length prepended, along with a file
+/// descriptor
+///
+/// The kernel installs a duplicate of `fd` in the receiving process;
+/// the caller keeps its own copy and may close it once this returns.
+pub(crate) fn send_fd(stream: &mut UnixStream, fd: RawFd, msg: &[u8]) -> Result<(), std::io::Error> {
+    let mut val = (msg.len() as u32).to_be_bytes().to_vec();
+    val.extend_from_slice(msg);
+
+    let mut iov = libc::iovec { iov_base: val.as_ptr() as *mut libc::c_void, iov_len: val.len() };
+    // SAFETY: CMSG_SPACE only computes a size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize];
+    // SAFETY: an all zero msghdr is a valid empty message
+    let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+    hdr.msg_iov = &mut iov;
+    hdr.msg_iovlen = 1;
+    hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    hdr.msg_controllen = control.len() as _;
+    // SAFETY: the control buffer has room for one header carrying a
+    // single fd, so the first header and its data are in bounds
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        libc::sendmsg(stream.as_raw_fd(), &hdr, 0)
+    };
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // the fd went with the first part, send whatever is left
+    stream.write_all(&val[sent as usize..])
+}
+
+/// Read a byte array of at most `max` bytes, with its length
+/// prepended, along with the file descriptor sent with it
+///
+/// The descriptor is received close-on-exec where supported and is
+/// closed when the returned `OwnedFd` is dropped. Fails with
+/// `InvalidData` if no descriptor came with the message.
+pub(crate) fn read_fd(stream: &mut UnixStream, max: usize) -> Result<(OwnedFd, Vec<u8>), std::io::Error> {
+    let mut len = [0u8; 4];
+    let mut iov = libc::iovec { iov_base: len.as_mut_ptr() as *mut libc::c_void, iov_len: len.len() };
+    // SAFETY: CMSG_SPACE only computes a size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE((MAX_FDS * mem::size_of::<RawFd>()) as u32) } as usize];
+    // SAFETY: an all zero msghdr is a valid empty message
+    let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+    hdr.msg_iov = &mut iov;
+    hdr.msg_iovlen = 1;
+    hdr.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    hdr.msg_controllen = control.len() as _;
+
+    #[cfg(target_os = "linux")]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(target_os = "linux"))]
+    let flags = 0;
+    // SAFETY: hdr points to buffers that outlive the call
+    let n = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut hdr, flags) };
+    if n < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if n == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+
+    // take ownership of every fd received, so none of them leak
+    let mut fds = Vec::new();
+    // SAFETY: the kernel filled in hdr.msg_control and
+    // msg_controllen, so walking the headers stays in bounds
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&hdr);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&hdr, cmsg);
+        }
+    }
+    let fd = match fds.into_iter().next() {
+        Some(fd) => fd,
+        None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            "no file descriptor received")),
+    };
+
+    // read the rest of the length, then the message
+    stream.read_exact(&mut len[n as usize..])?;
+    let len = u32::from_be_bytes(len);
+    if len as usize > max {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("message length {} exceeds {}", len, max)));
+    }
+    let mut buffer: Vec<u8> = vec![0; len as usize];
+    stream.read_exact(&mut buffer)?;
+    Ok((fd, buffer))
+}
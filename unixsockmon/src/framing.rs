@@ -0,0 +1,120 @@
+use std::io::{Read, Write};
+
+/// Width of the length prefix of a byte message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixWidth {
+    U16,
+    U32,
+    U64,
+}
+
+/// Byte order of the length prefix of a byte message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// How the length of a byte message is encoded ahead of it
+///
+/// Both ends of a connection must use the same framing; the default
+/// is a 4 byte big endian length, as used by `SockMonitor::read_bytes`
+/// and `SockMonitor::send_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    pub width: PrefixWidth,
+    pub endian: Endian
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing { width: PrefixWidth::U32, endian: Endian::Big }
+    }
+}
+
+impl Framing {
+    /// Create a framing with the given prefix width and byte order
+    pub fn new(width: PrefixWidth, endian: Endian) -> Self {
+        Framing { width, endian }
+    }
+
+    /// Read a length prepended byte array of at most `max` bytes
+    pub(crate) fn read_frame<S: Read>(&self, stream: &mut S, max: usize) -> Result<Vec<u8>, std::io::Error> {
+        let mut buffer = [0; 8];
+        let prefix = &mut buffer[..self.prefix_len()];
+
+        // read the length first
+        stream.read_exact(prefix)?;
+        if self.endian == Endian::Big {
+            prefix.reverse();
+        }
+        let len = u64::from_le_bytes(buffer);
+        // refuse oversized messages before allocating for them
+        if len > max as u64 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("message length {} exceeds {}", len, max)));
+        }
+
+        // read the rest of the message
+        let mut buffer: Vec<u8> = vec![0; len as usize];
+        stream.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Write a byte array with its length prepended
+    ///
+    /// Fails with `InvalidInput` if the length does not fit the
+    /// prefix.
+    pub(crate) fn write_frame<S: Write>(&self, stream: &mut S, msg: &[u8]) -> Result<(), std::io::Error> {
+        let width = self.prefix_len();
+        if width < 8 && msg.len() as u64 >= 1 << (width * 8) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("message length {} does not fit the length prefix", msg.len())));
+        }
+
+        // encode the length, then append the message bytes
+        let mut val = (msg.len() as u64).to_le_bytes()[..width].to_vec();
+        if self.endian == Endian::Big {
+            val.reverse();
+        }
+        val.extend_from_slice(msg);
+
+        // send the byte array
+        stream.write_all(&val)
+    }
+
+    fn prefix_len(&self) -> usize {
+        match self.width {
+            PrefixWidth::U16 => 2,
+            PrefixWidth::U32 => 4,
+            PrefixWidth::U64 => 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let mut buf = Vec::new();
+        Framing::default().write_frame(&mut buf, b"abc").unwrap();
+        assert_eq!(buf, [0, 0, 0, 3, b'a', b'b', b'c']);
+
+        let framing = Framing::new(PrefixWidth::U16, Endian::Little);
+        let mut buf = Vec::new();
+        framing.write_frame(&mut buf, b"abc").unwrap();
+        assert_eq!(buf, [3, 0, b'a', b'b', b'c']);
+        assert_eq!(framing.read_frame(&mut &buf[..], 16).unwrap(), b"abc");
+        // the other end must agree on the framing
+        assert!(Framing::default().read_frame(&mut &buf[..], 16).is_err());
+        assert!(framing.write_frame(&mut Vec::new(), &[0; 1 << 16]).is_err());
+
+        let framing = Framing::new(PrefixWidth::U64, Endian::Big);
+        let mut buf = Vec::new();
+        framing.write_frame(&mut buf, b"abc").unwrap();
+        assert_eq!(buf.len(), 11);
+        assert_eq!(framing.read_frame(&mut &buf[..], 16).unwrap(), b"abc");
+    }
+}
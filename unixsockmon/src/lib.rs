@@ -1,3 +1,18 @@
 
+#[cfg(feature = "tokio")]
+pub mod asyncmon;
+pub mod cred;
+pub mod error;
+mod fdpass;
+pub mod framing;
+pub mod pool;
 pub mod sockmonitor;
+pub mod tcpmonitor;
+#[cfg(feature = "tokio")]
+pub use crate::asyncmon::*;
+pub use crate::cred::*;
+pub use crate::error::*;
+pub use crate::framing::*;
+pub use crate::pool::*;
 pub use crate::sockmonitor::*;
+pub use crate::tcpmonitor::*;
@@ -1,4 +1,4 @@
-use std::{env, fs, thread, time};
+use std::{env, fs, time};
 use unixsockmon::SockMonitor;
 
 fn main() {
@@ -18,10 +18,8 @@ fn main() {
 }
 
 fn client(sock: &str, msg: &str) {
-    while !fs::metadata(sock).is_ok() {
-        thread::sleep(time::Duration::from_millis(500));
-    }        
-    let client = SockMonitor::new(sock);
+    let mut client = SockMonitor::new(sock);
+    client.set_connect_retry(20, time::Duration::from_millis(500));
     let resp = client.send_string(&format!("{}\n", msg));
     assert!(resp.is_ok());
     assert_eq!(resp.unwrap(), "OK");
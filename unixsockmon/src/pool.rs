@@ -0,0 +1,196 @@
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use crate::sockmonitor::{self, SockMonitor};
+
+/// Pool of client connections to a persistent `SockMonitor`
+///
+/// Instead of connecting for every message, `send` borrows one of up
+/// to `max` kept open connections and returns it afterwards, so the
+/// server must be running `SockMonitor::serve_persistent` with a
+/// line reader. Connections are opened as needed; when all of them
+/// are busy, `send` waits for one to be returned.
+///
+/// ```
+/// use unixsockmon::{SockClientPool, SockMonitor};
+/// use std::{thread, time, fs};
+///
+/// if fs::metadata("/tmp/mon_ex3.sock").is_ok() {
+///     fs::remove_file("/tmp/mon_ex3.sock").unwrap();
+/// }
+/// thread::spawn(|| {
+///     let mon = SockMonitor::new("/tmp/mon_ex3.sock");
+///     mon.serve_persistent(SockMonitor::read_line, move |req| {
+///         Ok(format!("OK {}", req))
+///     }, 2).unwrap();
+/// });
+///
+/// // waiting for the server to come up
+/// let mut pool = SockClientPool::new("/tmp/mon_ex3.sock", 2);
+/// pool.set_connect_retry(50, time::Duration::from_millis(100));
+/// for i in 0..10 {
+///     assert_eq!(pool.send(&format!("{}", i)).unwrap(), format!("OK {}", i));
+/// }
+/// assert_eq!(pool.idle(), 1);
+/// ```
+///
+pub struct SockClientPool {
+    sock: String,
+    max: usize,
+    connect_retry: Option<(u32, Duration)>,
+    state: Mutex<PoolState>,
+    returned: Condvar
+}
+
+struct PoolState {
+    idle: Vec<BufReader<UnixStream>>,
+    in_use: usize
+}
+
+impl SockClientPool {
+    /// Create a pool of up to `max` connections to the socket
+    pub fn new(sock: &str, max: usize) -> Self {
+        SockClientPool {
+            sock: sock.to_string(),
+            max: max.max(1),
+            connect_retry: None,
+            state: Mutex::new(PoolState { idle: Vec::new(), in_use: 0 }),
+            returned: Condvar::new()
+        }
+    }
+
+    /// Set how often opening a connection is retried; see
+    /// `SockMonitor::set_connect_retry`
+    pub fn set_connect_retry(&mut self, retries: u32, interval: Duration) {
+        self.connect_retry = Some((retries, interval));
+    }
+
+    /// Send a newline terminated string on a pooled connection and
+    /// return the response line
+    ///
+    /// If a reused connection turns out to be broken, e.g. because
+    /// the server restarted, it is dropped and the message is sent
+    /// once more on a fresh connection; a server that failed only
+    /// after reading the message may then see it twice.
+    pub fn send(&self, msg: &str) -> Result<String, std::io::Error> {
+        let (mut conn, reused) = self.take()?;
+        let mut res = SockMonitor::exchange_line(&mut conn, msg);
+        if res.is_err() && reused {
+            match self.connect() {
+                Ok(s) => {
+                    conn = BufReader::new(s);
+                    res = SockMonitor::exchange_line(&mut conn, msg);
+                }
+                Err(e) => res = Err(e),
+            }
+        }
+        self.give_back(res.is_ok().then_some(conn));
+        res
+    }
+
+    /// Number of open connections waiting to be used
+    pub fn idle(&self) -> usize {
+        self.lock().idle.len()
+    }
+
+    /// Number of connections currently sending
+    pub fn in_use(&self) -> usize {
+        self.lock().in_use
+    }
+
+    /// Largest number of connections the pool opens
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Take an idle connection, or open one if there is room;
+    /// returns whether the connection was reused
+    fn take(&self) -> Result<(BufReader<UnixStream>, bool), std::io::Error> {
+        let mut st = self.lock();
+        loop {
+            if let Some(conn) = st.idle.pop() {
+                st.in_use += 1;
+                return Ok((conn, true));
+            }
+            if st.in_use < self.max {
+                st.in_use += 1;
+                break;
+            }
+            st = self.returned.wait(st).unwrap_or_else(|e| e.into_inner());
+        }
+        // connect without holding the lock
+        drop(st);
+        match self.connect() {
+            Ok(s) => Ok((BufReader::new(s), false)),
+            Err(e) => {
+                self.give_back(None);
+                Err(e)
+            }
+        }
+    }
+
+    /// Open a connection using the connect retry policy
+    fn connect(&self) -> Result<UnixStream, std::io::Error> {
+        let (retries, interval) = self.connect_retry.unwrap_or((0, Duration::ZERO));
+        sockmonitor::connect_with_retry(&self.sock, retries, interval)
+    }
+
+    /// Return a connection to the pool, or just its slot if it broke
+    fn give_back(&self, conn: Option<BufReader<UnixStream>>) {
+        let mut st = self.lock();
+        st.in_use -= 1;
+        st.idle.extend(conn);
+        self.returned.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::{fs, thread, time};
+
+    #[test]
+    fn test_pool() {
+        if fs::metadata("/tmp/mon-pool.sock").is_ok() {
+            fs::remove_file("/tmp/mon-pool.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mut mon = SockMonitor::new("/tmp/mon-pool.sock");
+            // close connections idle for a while
+            mon.set_read_timeout(Some(time::Duration::from_millis(200)));
+            mon.serve_persistent(SockMonitor::read_line, move |req| {
+                Ok(format!("OK {}", req))
+            }, 4).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-pool.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(100));
+        }
+        let pool = Arc::new(SockClientPool::new("/tmp/mon-pool.sock", 2));
+        let senders: Vec<_> = (0..4).map(|i| {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                for j in 0..10 {
+                    let msg = format!("{} {}", i, j);
+                    assert_eq!(pool.send(&msg).unwrap(), format!("OK {}", msg));
+                }
+            })
+        }).collect();
+        for s in senders {
+            s.join().unwrap();
+        }
+        assert_eq!(pool.in_use(), 0);
+        assert!(pool.idle() >= 1 && pool.idle() <= 2);
+
+        // connections closed by the server are replaced
+        thread::sleep(time::Duration::from_millis(500));
+        assert_eq!(pool.send("again").unwrap(), "OK again");
+    }
+}
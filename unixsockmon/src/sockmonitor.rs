@@ -1,8 +1,23 @@
-
 use std::io::{Read, Write, BufReader, BufRead};
-use std::os::unix::net::{UnixStream, UnixListener};
+use std::os::unix::net::{UnixStream, UnixListener, UnixDatagram, SocketAddr};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::net::Shutdown;
+use std::ops::ControlFlow;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use asyncworkers::Workers;
+use crate::cred::PeerCred;
+use crate::error::{self, ErrorHook, MonitorError, Phase, ServerError};
+use crate::fdpass;
+use crate::framing::Framing;
 
 /// Generic Unix Named Socket Monitor
 ///
@@ -27,12 +42,9 @@ use std::fs;
 ///     }).unwrap();
 /// });
 ///
-/// //the sender
-/// while !fs::metadata("/tmp/mon_ex1.sock").is_ok() {
-///     thread::sleep(time::Duration::from_millis(500));
-/// }
-///
-/// let client = SockMonitor::new("/tmp/mon_ex1.sock");
+/// //the sender, waiting for the server to come up
+/// let mut client = SockMonitor::new("/tmp/mon_ex1.sock");
+/// client.set_connect_retry(50, time::Duration::from_millis(100));
 /// // string can be with or without newline
 /// let resp = client.send_string("the quick brown fox jumps over the lazy dog");
 /// assert!(resp.is_ok());
@@ -56,12 +68,9 @@ use std::fs;
 ///     }).unwrap();
 /// });
 ///
-/// //the sender
-/// while !fs::metadata("/tmp/mon_ex2.sock").is_ok() {
-///     thread::sleep(time::Duration::from_millis(500));
-/// }
-///
-/// let client = SockMonitor::new("/tmp/mon_ex2.sock");
+/// //the sender, waiting for the server to come up
+/// let mut client = SockMonitor::new("/tmp/mon_ex2.sock");
+/// client.set_connect_retry(50, time::Duration::from_millis(100));
 /// // message is a byte array with a leading message length
 /// let msg = "the quick brown fox jumps over the lazy dog";
 /// let resp = client.send_bytes(msg.as_bytes());
@@ -69,46 +78,344 @@ use std::fs;
 /// assert_eq!(resp.unwrap(), "OK");
 /// ```
 ///
+/// On Linux a socket name starting with `@`, like `@myservice`, is
+/// bound in the abstract namespace instead: there is no socket file
+/// to clean up and the name goes away with the process. `with_mode`
+/// does not apply to such sockets. Elsewhere the name is an ordinary
+/// path.
+///
 pub struct SockMonitor {
-    sock: String
+    sock: String,
+    max_message_len: usize,
+    max_line_len: usize,
+    framing: Framing,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    connect_retry: Option<(u32, Duration)>,
+    send_retry: Option<(u32, Duration)>,
+    on_error: ErrorHook,
+    framed_responses: bool,
+    accept_backoff: (Duration, Duration),
+    mode: Option<u32>,
+    backlog: Option<i32>,
+    stop: Arc<AtomicBool>,
+    // connection held by `request`
+    conn: Option<BufReader<UnixStream>>
+}
+
+/// Handle for stopping a serving `SockMonitor`
+///
+/// Obtained from `SockMonitor::shutdown_handle` before serving; it
+/// can be cloned and moved to another thread, e.g. a signal handler.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sock: String,
+    stop: Arc<AtomicBool>
+}
+
+impl ShutdownHandle {
+    /// Stop the accept loop of the monitor
+    ///
+    /// `serve` returns once the connection it is processing, if any,
+    /// has been answered; `serve_concurrent` also waits for the
+    /// connections already handed to its workers. `serve_persistent`
+    /// answers the requests in flight and then closes every open
+    /// connection, without waiting for its clients to leave. The
+    /// socket file is removed. A monitor that was stopped before it started serving
+    /// returns right away.
+    pub fn stop(&self) {
+        if !self.stop.swap(true, Ordering::SeqCst) {
+            // wake up the blocking accept with a dummy connection, or
+            // a datagram server with an empty datagram
+            if connect(&self.sock).is_err() {
+                let _ = send_datagram(&self.sock, &[]);
+            }
+        }
+    }
 }
 
+/// Longest datagram `serve_datagram` receives, 64 KiB
+pub const MAX_DATAGRAM_LEN: usize = 64 * 1024;
+
+/// Message a client sends to check the server is alive, a single
+/// ENQ control character (0x05)
+///
+/// The server answers it with `PONG` itself, without calling the
+/// handler. A ping is framed like any other message on the
+/// connection: the line `"\x05\n"` for a line reader, or a frame
+/// holding the single byte 0x05 for a byte reader. The pong comes
+/// back the way responses do, e.g. as the line `"\x06\n"`.
+/// Servers writing binary responses, `serve_binary` and `serve_raw`,
+/// pass pings on to their handler.
+pub const PING: &str = "\u{5}";
+
+/// Response of the server to a `PING`, a single ACK control
+/// character (0x06)
+pub const PONG: &str = "\u{6}";
+
+/// Start of the response to a request the handler failed, followed
+/// by the message of the handler error
+///
+/// Newlines in the message are sent as spaces, so the response fits
+/// on a line. Clients can tell it apart from a regular response with
+/// `SockMonitor::parse_reply`.
+pub const ERR_PREFIX: &str = "ERR: ";
+
+/// Status byte of a binary response the handler succeeded with
+///
+/// Servers writing binary responses, `serve_binary` and `serve_raw`,
+/// start every response frame with a status byte, this one or
+/// `ERR_FRAME`, followed by the payload; see
+/// `SockMonitor::parse_binary_reply`.
+pub const OK_FRAME: u8 = 0x00;
+
+/// Status byte of a binary response the handler failed, a NAK
+/// control character (0x15), followed by the message of the handler
+/// error
+pub const ERR_FRAME: u8 = 0x15;
+
+/// Largest message `read_bytes` accepts, 16 MiB
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Longest line `read_line` accepts, not counting the newline, 1 MiB
+pub const DEFAULT_MAX_LINE_LEN: usize = 1024 * 1024;
+
 impl SockMonitor {
     /// Create a new named socket monitor
     pub fn new(sock: &str) -> Self {
-        SockMonitor { sock: sock.to_string() }
+        SockMonitor {
+            sock: sock.to_string(),
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            framing: Framing::default(),
+            read_timeout: None,
+            write_timeout: None,
+            idle_timeout: None,
+            connect_retry: None,
+            send_retry: None,
+            on_error: error::log_error(),
+            framed_responses: false,
+            accept_backoff: (Duration::from_millis(10), Duration::from_secs(1)),
+            mode: None,
+            backlog: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            conn: None
+        }
+    }
+
+    /// Set the permissions of the socket file, e.g. `0o600` for a
+    /// control socket only its owner may connect to
+    ///
+    /// The mode is applied right after binding, so for a moment the
+    /// socket has the permissions given by the umask; a client may
+    /// connect in that window. The umask is process wide and so is
+    /// left alone here; to close the window, run the server with a
+    /// restrictive umask or put the socket in a private directory.
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set how many connections may wait to be accepted
+    ///
+    /// Servers expecting bursts of connections, e.g. clients all
+    /// reconnecting after a restart, may need a deeper queue than
+    /// the one picked by the standard library; once it is full,
+    /// further clients block in `connect` or are refused. Values
+    /// above `SOMAXCONN` are clamped to it, and the kernel caps the
+    /// queue further at its own limit, `net.core.somaxconn` on Linux.
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = Some(backlog.min(libc::SOMAXCONN as u32) as i32);
+        self
+    }
+
+    /// Depth of the accept queue set with `with_backlog`, if any
+    pub fn backlog(&self) -> Option<u32> {
+        self.backlog.map(|b| b as u32)
+    }
+
+    /// Get a handle for stopping the monitor once it is serving
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle { sock: self.sock.clone(), stop: Arc::clone(&self.stop) }
+    }
+
+    /// Set the largest message length the byte reader accepts
+    ///
+    /// Applies to the reader returned by `bytes_reader`; a declared
+    /// length above it is rejected before anything is allocated.
+    pub fn set_max_message_len(&mut self, len: usize) {
+        self.max_message_len = len;
+    }
+
+    /// Largest message length the byte reader accepts
+    pub fn max_message_len(&self) -> usize {
+        self.max_message_len
+    }
+
+    /// Set the longest line the line reader accepts
+    ///
+    /// Applies to the reader returned by `line_reader` and to
+    /// `serve_json`; a client sending more than this without a newline
+    /// is rejected once the limit is reached, so it can't make the
+    /// server buffer without bound.
+    pub fn set_max_line_len(&mut self, len: usize) {
+        self.max_line_len = len;
+    }
+
+    /// Longest line the line reader accepts
+    pub fn max_line_len(&self) -> usize {
+        self.max_line_len
+    }
+
+    /// Set how the length of byte messages is encoded
+    ///
+    /// Used by `bytes_reader`, `serve_raw` and `serve_binary` on the
+    /// server side and by `send_bytes` and `send_bytes_binary` on the
+    /// client side, so both ends must be configured alike. The plain
+    /// `read_bytes` and `read_raw` readers always use the default.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// How the length of byte messages is encoded
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Write string responses with their length prepended
+    ///
+    /// By default `serve` and `serve_concurrent` write the bare
+    /// response and close the connection, and `serve_persistent` ends
+    /// each response with a newline. Framed responses, using the
+    /// configured framing, tell clients exactly where a response ends
+    /// even on a kept-alive connection; see `request_bytes`.
+    pub fn set_framed_responses(&mut self, framed: bool) {
+        self.framed_responses = framed;
+    }
+
+    /// Set a hook to be called with the errors of a serving monitor
+    ///
+    /// Errors reading, handling or answering a message, or accepting
+    /// a connection, only affect that connection; they are passed to
+    /// the hook, e.g. to route them into structured logging. Without
+    /// a hook they are printed to stderr.
+    pub fn on_error<F>(&mut self, hook: F)
+        where F: Fn(MonitorError) + Send + Sync + 'static
+    {
+        self.on_error = Arc::new(hook);
+    }
+
+    /// Set how long to back off after failing to accept a connection
+    ///
+    /// After an accept error, e.g. `EMFILE` when out of file
+    /// descriptors, the server sleeps for `initial`, doubling the
+    /// sleep on each further error up to `max`, so it does not spin
+    /// while the condition lasts; a successful accept starts over.
+    /// The default is 10ms up to 1s.
+    pub fn set_accept_backoff(&mut self, initial: Duration, max: Duration) {
+        self.accept_backoff = (initial, max);
+    }
+
+    /// Set the read timeout of accepted connections
+    ///
+    /// A client that does not send its message in time has the read
+    /// fail with `WouldBlock`, which is logged and the connection
+    /// dropped like any other read error. `None`, the default, waits
+    /// forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Set the write timeout of accepted connections
+    ///
+    /// Like `set_read_timeout`, for a client that does not read the
+    /// response.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Close persistent connections with no complete request within
+    /// `timeout`
+    ///
+    /// `serve_persistent` gives each request this long to arrive in
+    /// full, counted from when the server starts waiting for it, in
+    /// place of the read timeout set with `set_read_timeout`. A
+    /// client that goes quiet, or trickles in a request too slowly,
+    /// has its connection closed and reported to the error hook with
+    /// `Phase::Idle`, unlike a client closing the connection, which
+    /// is not reported. This frees the workers held by clients that
+    /// connect and go quiet.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = Some(timeout);
     }
 
     /// Read a newline terminated string; return string has
     /// the newline stripped.
-    pub fn read_line(stream: &mut UnixStream) -> Result<String, std::io::Error> {
+    ///
+    /// A connection closed before anything was sent is reported as
+    /// `UnexpectedEof`. Lines longer than `DEFAULT_MAX_LINE_LEN` are
+    /// rejected with `InvalidData`; see `line_reader` for a
+    /// configurable limit.
+    pub fn read_line<S: Read>(stream: &mut S) -> Result<String, std::io::Error> {
+        Self::read_line_max(stream, DEFAULT_MAX_LINE_LEN)
+    }
+
+    /// Line reader using this monitor's `max_line_len`
+    pub fn line_reader<S: Read>(&self) -> impl Fn(&mut S) -> Result<String, std::io::Error> + Send + Sync + 'static {
+        let max = self.max_line_len;
+        move |stream| Self::read_line_max(stream, max)
+    }
+
+    fn read_line_max<S: Read>(stream: &mut S, max: usize) -> Result<String, std::io::Error> {
         let mut reader = BufReader::new(stream);
-        let mut msg = String::new();
+        let mut msg = Vec::new();
 
-        reader.read_line(&mut msg)?;
-        if msg.ends_with('\n') {
+        // never buffer more than the limit plus the newline
+        let limit = (max as u64).saturating_add(1);
+        if reader.by_ref().take(limit).read_until(b'\n', &mut msg)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if msg.ends_with(b"\n") {
             msg.pop();
+        } else if msg.len() > max {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("line exceeds {} bytes", max)));
         }
-        Ok(msg)
+        String::from_utf8(msg).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
     }
 
     /// Read a byte array and return as string
-    pub fn read_bytes(stream: &mut UnixStream) -> Result<String, std::io::Error> {
-        let mut buffer = [0; 4];
-        let len;
+    ///
+    /// Messages longer than `DEFAULT_MAX_MESSAGE_LEN` are rejected
+    /// with `InvalidData`; see `bytes_reader` for a configurable limit.
+    pub fn read_bytes<S: Read>(stream: &mut S) -> Result<String, std::io::Error> {
+        Self::read_bytes_max(stream, Framing::default(), DEFAULT_MAX_MESSAGE_LEN)
+    }
 
-        // read 4 byte length first
-        stream.read_exact(&mut buffer)?;
-        len = u32::from_be_bytes(buffer);
+    /// Read a byte array as is
+    ///
+    /// Unlike `read_bytes` the bytes need not be UTF-8, for binary
+    /// protocols; the same `DEFAULT_MAX_MESSAGE_LEN` limit applies.
+    pub fn read_raw<S: Read>(stream: &mut S) -> Result<Vec<u8>, std::io::Error> {
+        Framing::default().read_frame(stream, DEFAULT_MAX_MESSAGE_LEN)
+    }
+
+    /// Byte array reader using this monitor's `max_message_len` and
+    /// framing
+    pub fn bytes_reader<S: Read>(&self) -> impl Fn(&mut S) -> Result<String, std::io::Error> + Send + Sync + 'static {
+        let (framing, max) = (self.framing, self.max_message_len);
+        move |stream| Self::read_bytes_max(stream, framing, max)
+    }
 
-        // read the rest of the message
-        let mut buffer: Vec<u8> = vec![0; len as usize];
-        stream.read_exact(&mut buffer)?;
+    /// Read a byte array of at most `max` bytes and return as string
+    fn read_bytes_max<S: Read>(stream: &mut S, framing: Framing, max: usize) -> Result<String, std::io::Error> {
+        let buffer = framing.read_frame(stream, max)?;
         let msg = match std::str::from_utf8(&buffer) {
             Ok(m) => m,
             Err(e) => {
                 eprintln!("{}", e);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, "cannot convert bytes!"));
+                return Err(std::io::Error::other("cannot convert bytes!"));
             }
         };
         Ok(msg.to_string())
@@ -121,52 +428,628 @@ impl SockMonitor {
               R: Fn(&mut UnixStream) -> Result<String, std::io::Error>,
               R: Send + 'static
      {
-        // cleanup any stale named sockets
-        if fs::metadata(&self.sock).is_ok() {
-            fs::remove_file(&self.sock)?;
-        }
+        let (listener, _file) = self.bind()?;
 
-        // create the listener socket
-        let listener = UnixListener::bind(&self.sock)?;
+        // accept and process each connection
+        let reply = self.reply(Reply::Close);
+        self.accept(listener, |s| Self::handle(s, &reader, &handler, reply, &*self.on_error));
+        Ok(())
+    }
+
+    /// Serve the named socket, passing shared application state to
+    /// the handler
+    ///
+    /// Like `serve`, with `ctx` handed to every handler call, so the
+    /// handler can be written, and tested, against explicit state
+    /// instead of captures.
+    pub fn serve_with_ctx<C, H, R>(&self, ctx: Arc<C>, reader: R, handler: H) -> Result<(), std::io::Error>
+        where C: Send + Sync + 'static,
+              H: Fn(&C, String) -> Result<String, Box<dyn Error>>,
+              H: Send + 'static,
+              R: Fn(&mut UnixStream) -> Result<String, std::io::Error>,
+              R: Send + 'static
+    {
+        self.serve(reader, move |msg| handler(&ctx, msg))
+    }
+
+    /// Serve the named socket, passing the credentials of the
+    /// connecting process to the handler
+    ///
+    /// Lets the handler reject commands from unauthorized users.
+    /// Peer credentials are only supported on Linux; elsewhere every
+    /// connection is logged and dropped.
+    pub fn serve_with_cred<H, R>(&self, reader: R, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(PeerCred, String) -> Result<String, Box<dyn Error>>,
+              H: Send + 'static,
+              R: Fn(&mut UnixStream) -> Result<String, std::io::Error>,
+              R: Send + 'static
+    {
+        let (listener, _file) = self.bind()?;
+
+        let reply = self.reply(Reply::Close);
+        // accept and process each connection
+        self.accept(listener, |s| {
+            let cred = match PeerCred::of(&s) {
+                Ok(c) => c,
+                Err(e) => {
+                    (self.on_error)(MonitorError::new(Phase::Accept, e));
+                    return;
+                }
+            };
+            Self::handle(s, &reader, &|msg| handler(cred, msg), reply, &*self.on_error);
+        });
+        Ok(())
+    }
+
+    /// Serve the named socket with binary responses
+    ///
+    /// Like `serve`, but the handler returns bytes, which are written
+    /// with a length prepended as `read_bytes` expects, so they need
+    /// not be UTF-8 and the client knows where they end; see
+    /// `send_bytes_binary`. Each response starts with a status byte,
+    /// `OK_FRAME`, or `ERR_FRAME` when the handler failed.
+    pub fn serve_binary<H, R>(&self, reader: R, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<Vec<u8>, Box<dyn Error>>,
+              H: Send + 'static,
+              R: Fn(&mut UnixStream) -> Result<String, std::io::Error>,
+              R: Send + 'static
+    {
+        self.serve_frames(reader, handler)
+    }
+
+    /// Serve the named socket with raw byte requests and responses
+    ///
+    /// Requests are read with the `read_bytes` framing, limited to
+    /// `max_message_len`, and handed over as is, without requiring
+    /// UTF-8; responses are written as in `serve_binary`.
+    pub fn serve_raw<H>(&self, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>>,
+              H: Send + 'static
+    {
+        let (framing, max) = (self.framing, self.max_message_len);
+        self.serve_frames(move |s| framing.read_frame(s, max), handler)
+    }
+
+    /// Serve the named socket, writing length prepended responses
+    fn serve_frames<M, H, R>(&self, reader: R, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(M) -> Result<Vec<u8>, Box<dyn Error>>,
+              R: Fn(&mut UnixStream) -> Result<M, std::io::Error>
+    {
+        let (listener, _file) = self.bind()?;
+
+        // accept and process each connection
+        self.accept(listener, |mut s| {
+            let msg = match reader(&mut s) {
+                Ok(m) => m,
+                Err(e) => {
+                    (self.on_error)(MonitorError::new(Phase::Read, e));
+                    return;
+                }
+            };
+            let resp = match handler(msg) {
+                Ok(payload) => [&[OK_FRAME], &payload[..]].concat(),
+                Err(e) => {
+                    let resp = [&[ERR_FRAME], e.to_string().as_bytes()].concat();
+                    (self.on_error)(MonitorError::new(Phase::Handle, e));
+                    resp
+                }
+            };
+            self.framing.write_frame(&mut s, &resp).unwrap_or_else(|e| {
+                (self.on_error)(MonitorError::new(Phase::Write, e));
+            });
+        });
+        Ok(())
+    }
+
+    /// Read a newline delimited JSON value
+    ///
+    /// Reads a line as in `read_line` and deserializes it; malformed
+    /// JSON fails with `InvalidData`.
+    #[cfg(feature = "json")]
+    pub fn read_json<T, S>(stream: &mut S) -> Result<T, std::io::Error>
+        where T: serde::de::DeserializeOwned,
+              S: Read
+    {
+        let line = Self::read_line(stream)?;
+        serde_json::from_str(&line).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Serve the named socket with JSON requests and responses
+    ///
+    /// Each request is a line holding a JSON value, which is handed
+    /// to the handler deserialized; the response is written back as
+    /// a line of JSON, so the socket can be driven with `nc` and
+    /// `jq`. A request that does not deserialize, like a handler
+    /// error, is answered with an `ERR_PREFIX` line.
+    #[cfg(feature = "json")]
+    pub fn serve_json<Req, Resp, H>(&self, handler: H) -> Result<(), std::io::Error>
+        where Req: serde::de::DeserializeOwned,
+              Resp: serde::Serialize,
+              H: Fn(Req) -> Result<Resp, Box<dyn Error>>,
+              H: Send + 'static
+    {
+        let (listener, _file) = self.bind()?;
+
+        let handler = move |req: String| -> Result<String, Box<dyn Error>> {
+            let resp = handler(serde_json::from_str(&req)?)?;
+            Ok(serde_json::to_string(&resp)?)
+        };
+        let reply = self.reply(Reply::Line);
+        let reader = self.line_reader();
+        self.accept(listener, |s| Self::handle(s, &reader, &handler, reply, &*self.on_error));
+        Ok(())
+    }
+
+    /// Read a byte array sent with `send_fd`, along with the file
+    /// descriptor passed with it
+    ///
+    /// The descriptor is a new one owned by the caller, closed when
+    /// the returned `OwnedFd` is dropped; use `into_raw_fd` to keep
+    /// it open beyond that. Fails with `InvalidData` if the message
+    /// carries no descriptor.
+    pub fn read_fd(stream: &mut UnixStream) -> Result<(OwnedFd, Vec<u8>), std::io::Error> {
+        fdpass::read_fd(stream, DEFAULT_MAX_MESSAGE_LEN)
+    }
+
+    /// Serve the named socket with requests carrying a file descriptor
+    ///
+    /// Each request is read as in `read_fd`, limited to
+    /// `max_message_len`, and the handler receives the descriptor
+    /// with the payload; it is closed once the handler returns unless
+    /// the handler keeps it. A handler error is answered with
+    /// `ERR_PREFIX` and its message.
+    pub fn serve_fd<H>(&self, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(OwnedFd, Vec<u8>) -> Result<String, Box<dyn Error>>,
+              H: Send + 'static
+    {
+        let (listener, _file) = self.bind()?;
 
         // accept and process each connection
-        for stream in listener.incoming() {
+        self.accept(listener, |mut s| {
+            let (fd, msg) = match fdpass::read_fd(&mut s, self.max_message_len) {
+                Ok(m) => m,
+                Err(e) => {
+                    (self.on_error)(MonitorError::new(Phase::Read, e));
+                    return;
+                }
+            };
+            let resp = handler(fd, msg).unwrap_or_else(|e| {
+                let resp = error_reply(&*e);
+                (self.on_error)(MonitorError::new(Phase::Handle, e));
+                resp
+            });
+            s.write_all(resp.as_bytes()).unwrap_or_else(|e| {
+                (self.on_error)(MonitorError::new(Phase::Write, e));
+            });
+        });
+        Ok(())
+    }
+
+    /// Serve the named socket, handling up to `workers` connections
+    /// in parallel
+    ///
+    /// Each accepted connection is handed to a pool of `workers`
+    /// threads, so a slow client no longer holds up the others;
+    /// connections beyond that wait in the pool's queue. The reader
+    /// and handler are shared by the workers and so must be `Sync`.
+    pub fn serve_concurrent<H, R>(&self, reader: R, handler: H, workers: usize) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<String, Box<dyn Error>>,
+              H: Send + Sync + 'static,
+              R: Fn(&mut UnixStream) -> Result<String, std::io::Error>,
+              R: Send + Sync + 'static
+    {
+        let (listener, _file) = self.bind()?;
+        let pool = Workers::try_new(workers)?;
+        let reader = Arc::new(reader);
+        let handler = Arc::new(handler);
+        let reply = self.reply(Reply::Close);
+
+        // accept each connection and queue it for the workers
+        self.accept(listener, |s| {
+            let reader = Arc::clone(&reader);
+            let handler = Arc::clone(&handler);
+            let on_error = Arc::clone(&self.on_error);
+            if pool.submit(move || Self::handle(s, &*reader, &*handler, reply, &*on_error)).is_err() {
+                (self.on_error)(MonitorError::new(Phase::Accept, "worker pool closed"));
+            }
+        });
+        // dropping the pool waits for the queued connections
+        Ok(())
+    }
+
+    /// Serve the named socket, keeping each connection open for
+    /// any number of requests
+    ///
+    /// After writing a response, followed by a newline, the server
+    /// reads the next message from the same connection until the
+    /// client disconnects; see `request` for the client side. As
+    /// with `serve_concurrent`, up to `workers` connections are
+    /// served in parallel. Stopping the server closes the connections
+    /// still open once their current request is answered. The reader
+    /// is handed each connection as a `PersistentConn`, which keeps
+    /// what was read ahead for the next request, so clients may send
+    /// requests without waiting for each response.
+    pub fn serve_persistent<H, R>(&self, reader: R, handler: H, workers: usize) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<String, Box<dyn Error>>,
+              H: Send + Sync + 'static,
+              R: Fn(&mut PersistentConn) -> Result<String, std::io::Error>,
+              R: Send + Sync + 'static
+    {
+        self.serve_persistent_flow(reader, move |msg| handler(msg).map(ControlFlow::Continue), workers)
+    }
+
+    /// Serve the named socket with persistent connections the
+    /// handler can close
+    ///
+    /// Like `serve_persistent`, but the handler decides what happens
+    /// after each response: `ControlFlow::Continue` keeps reading
+    /// from the connection, `ControlFlow::Break` writes its response
+    /// and then closes the connection, e.g. for a `quit` command. A
+    /// handler error is answered with `ERR_PREFIX` and its message and
+    /// keeps the connection.
+    pub fn serve_persistent_flow<H, R>(&self, reader: R, handler: H, workers: usize) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<ControlFlow<String, String>, Box<dyn Error>>,
+              H: Send + Sync + 'static,
+              R: Fn(&mut PersistentConn) -> Result<String, std::io::Error>,
+              R: Send + Sync + 'static
+    {
+        let (listener, _file) = self.bind()?;
+        let pool = Workers::try_new(workers)?;
+        let reader = Arc::new(reader);
+        let handler = Arc::new(handler);
+        let reply = self.reply(Reply::Line);
+        let idle = self.idle_timeout;
+        let conns = Arc::new(Connections::default());
+
+        self.accept(listener, |s| {
+            let id = match conns.add(&s) {
+                Ok(id) => id,
+                Err(e) => {
+                    (self.on_error)(MonitorError::new(Phase::Accept, e));
+                    return;
+                }
+            };
+            let reader = Arc::clone(&reader);
+            let handler = Arc::clone(&handler);
+            let on_error = Arc::clone(&self.on_error);
+            let stop = Arc::clone(&self.stop);
+            let open = Arc::clone(&conns);
+            let served = pool.submit(move || {
+                Self::handle_persistent(s, &*reader, &*handler, reply, idle, &stop, &*on_error);
+                open.remove(id);
+            });
+            if served.is_err() {
+                conns.remove(id);
+                (self.on_error)(MonitorError::new(Phase::Accept, "worker pool closed"));
+            }
+        });
+        // clients may stay connected forever; close their connections
+        // so dropping the pool only waits for the requests in flight
+        conns.close_all();
+        Ok(())
+    }
+
+    /// Hook receiving the errors while serving
+    #[cfg(feature = "tokio")]
+    pub(crate) fn error_hook(&self) -> ErrorHook {
+        Arc::clone(&self.on_error)
+    }
+
+    /// Backoff between consecutive accept errors
+    #[cfg(feature = "tokio")]
+    pub(crate) fn accept_backoff(&self) -> Backoff {
+        Backoff::new(self.accept_backoff.0, self.accept_backoff.1)
+    }
+
+    /// How a response is written after `end`, the plain one
+    fn reply(&self, end: Reply) -> Reply {
+        if self.framed_responses {
+            Reply::Framed(self.framing)
+        } else {
+            end
+        }
+    }
+
+    /// Accept connections until stopped, passing each one to `f`
+    fn accept<F>(&self, listener: UnixListener, mut f: F)
+        where F: FnMut(UnixStream)
+    {
+        let mut backoff = Backoff::new(self.accept_backoff.0, self.accept_backoff.1);
+        while !self.stop.load(Ordering::SeqCst) {
+            let stream = listener.accept();
+            // the connection may be the dummy one from `stop`
+            if self.stop.load(Ordering::SeqCst) {
+                break;
+            }
             match stream {
-                Ok(mut s) => {
-                    // read message from socket
-                    let msg = match reader(&mut s) {
-                        Ok(m) => m,
-                        Err(e) => {
-                            eprintln!("Monitor::serve:read {}", e);
-                            continue;
-                        }
-                    };
-                    // process message
-                    match handler(msg) {
-                        Err(e) => {
-                            eprintln!("Monitor::serve:handle {}", e);
-                            s.write_all("ERR".to_string().as_bytes()).unwrap_or_else(|e| {
-                                eprintln!("Monitor::serve:write:ERR {}", e);
-                            });
-                        }
-                        Ok(r) => {
-                            s.write_all(r.as_bytes()).unwrap_or_else(|e| {
-                                eprintln!("Monitor::serve:write:{} {}", r, e);
-                            });
-                        }
+                Ok((s, _)) => {
+                    backoff.reset();
+                    let timeouts = s.set_read_timeout(self.read_timeout)
+                        .and_then(|_| s.set_write_timeout(self.write_timeout));
+                    match timeouts {
+                        Ok(()) => f(s),
+                        Err(e) => (self.on_error)(MonitorError::new(Phase::Accept, e)),
                     }
                 }
                 Err(e) => {
-                    eprintln!("Monitor::serve:accept {}", e);
+                    (self.on_error)(MonitorError::new(Phase::Accept, e));
+                    std::thread::sleep(backoff.next());
+                }
+            }
+        }
+    }
+
+    /// Remove the socket file once serving stops
+    fn remove_socket(&self) {
+        if !is_abstract(&self.sock) {
+            fs::remove_file(&self.sock).unwrap_or_else(|e| {
+                (self.on_error)(MonitorError::new(Phase::Accept, e));
+            });
+        }
+    }
+
+    /// Bind the named socket, removing a stale one first
+    ///
+    /// The socket file is removed again when the returned guard is
+    /// dropped.
+    pub(crate) fn bind(&self) -> Result<(UnixListener, SocketFile<'_>), std::io::Error> {
+        // abstract sockets have no file to clean up or chmod
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.sock.strip_prefix('@') {
+            let addr = SocketAddr::from_abstract_name(name)?;
+            let listener = UnixListener::bind_addr(&addr)?;
+            self.set_backlog(&listener)?;
+            return Ok((listener, SocketFile(self)));
+        }
+
+        // create the listener socket
+        self.remove_stale()?;
+        let listener = UnixListener::bind(&self.sock)?;
+        let file = SocketFile(self);
+        self.set_backlog(&listener)?;
+        self.set_mode()?;
+        Ok((listener, file))
+    }
+
+    /// Apply the accept queue depth set with `with_backlog`
+    fn set_backlog(&self, listener: &UnixListener) -> Result<(), std::io::Error> {
+        if let Some(backlog) = self.backlog {
+            // listening again on a listening socket only changes the
+            // depth of its queue
+            // SAFETY: the listener owns a valid socket
+            if unsafe { libc::listen(listener.as_raw_fd(), backlog) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind the named datagram socket, removing a stale one first
+    fn bind_datagram(&self) -> Result<(UnixDatagram, SocketFile<'_>), std::io::Error> {
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.sock.strip_prefix('@') {
+            let addr = SocketAddr::from_abstract_name(name)?;
+            return Ok((UnixDatagram::bind_addr(&addr)?, SocketFile(self)));
+        }
+
+        self.remove_stale()?;
+        let sock = UnixDatagram::bind(&self.sock)?;
+        let file = SocketFile(self);
+        self.set_mode()?;
+        Ok((sock, file))
+    }
+
+    /// Cleanup any stale named socket
+    fn remove_stale(&self) -> Result<(), std::io::Error> {
+        if fs::metadata(&self.sock).is_ok() {
+            fs::remove_file(&self.sock)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the permissions set with `with_mode`
+    fn set_mode(&self) -> Result<(), std::io::Error> {
+        if let Some(mode) = self.mode {
+            fs::set_permissions(&self.sock, fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+
+    /// Serve the named socket as a datagram socket
+    ///
+    /// For fire-and-forget messages, without a connection for each
+    /// one: every datagram received is passed to the handler along
+    /// with the sender's address, if the sender bound its socket to
+    /// one. The handler can reply by sending a datagram to that
+    /// address from a socket of its own. Datagrams longer than
+    /// `MAX_DATAGRAM_LEN` are truncated.
+    pub fn serve_datagram<H>(&self, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(Vec<u8>, Option<SocketAddr>)
+    {
+        let (sock, _file) = self.bind_datagram()?;
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+
+        while !self.stop.load(Ordering::SeqCst) {
+            let res = sock.recv_from(&mut buf);
+            // the datagram may be the empty one from `stop`
+            if self.stop.load(Ordering::SeqCst) {
+                break;
+            }
+            match res {
+                Ok((len, addr)) => {
+                    let addr = if addr.is_unnamed() { None } else { Some(addr) };
+                    handler(buf[..len].to_vec(), addr);
+                }
+                Err(e) => {
+                    (self.on_error)(MonitorError::new(Phase::Read, e));
                 }
             }
         }
         Ok(())
     }
 
+    /// Read one message from an accepted connection, process it and
+    /// write the response
+    pub(crate) fn handle<S, H, R>(mut s: S, reader: &R, handler: &H, reply: Reply, on_error: &dyn Fn(MonitorError))
+        where S: Read + Write,
+              H: Fn(String) -> Result<String, Box<dyn Error>>,
+              R: Fn(&mut S) -> Result<String, std::io::Error>
+    {
+        // read message from socket
+        let msg = match reader(&mut s) {
+            Ok(m) => m,
+            Err(e) => {
+                on_error(MonitorError::new(Phase::Read, e));
+                return;
+            }
+        };
+        let _ = Self::respond(&mut s, handler, msg, reply, on_error);
+    }
+
+    /// Process messages from an accepted connection until the client
+    /// disconnects, stays idle for longer than `idle`, the handler
+    /// breaks or the server stops
+    pub(crate) fn handle_persistent<H, R>(s: UnixStream, reader: &R, handler: &H, reply: Reply, idle: Option<Duration>,
+        stop: &AtomicBool, on_error: &dyn Fn(MonitorError))
+        where H: Fn(String) -> Result<ControlFlow<String, String>, Box<dyn Error>>,
+              R: Fn(&mut PersistentConn) -> Result<String, std::io::Error>
+    {
+        let close = Cell::new(false);
+        let handler = |msg| handler(msg).map(|flow| match flow {
+            ControlFlow::Continue(resp) => resp,
+            ControlFlow::Break(resp) => {
+                close.set(true);
+                resp
+            }
+        });
+        let timeout = match s.read_timeout() {
+            Ok(t) => t,
+            Err(e) => {
+                on_error(MonitorError::new(Phase::Read, e));
+                return;
+            }
+        };
+        let mut conn = PersistentConn { reader: BufReader::new(Deadline { stream: s, deadline: None }) };
+        while !close.get() && !stop.load(Ordering::SeqCst) {
+            // the whole request has to arrive within the idle timeout
+            conn.reader.get_mut().deadline = idle.map(|idle| Instant::now() + idle);
+            let msg = match reader(&mut conn) {
+                Ok(m) => m,
+                // the client is done
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return,
+                Err(e) if idle.is_some() && matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    on_error(MonitorError::new(Phase::Idle, format!("no complete request within {:?}", idle.unwrap())));
+                    return;
+                }
+                Err(e) => {
+                    on_error(MonitorError::new(Phase::Read, e));
+                    return;
+                }
+            };
+            let conn = conn.reader.get_mut();
+            if idle.is_some() {
+                conn.deadline = None;
+                if let Err(e) = conn.stream.set_read_timeout(timeout) {
+                    on_error(MonitorError::new(Phase::Read, e));
+                    return;
+                }
+            }
+            if Self::respond(&mut conn.stream, &handler, msg, reply, on_error).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Process a message and write the response
+    fn respond<S, H>(s: &mut S, handler: &H, msg: String, reply: Reply, on_error: &dyn Fn(MonitorError)) -> Result<(), ()>
+        where S: Write,
+              H: Fn(String) -> Result<String, Box<dyn Error>>
+    {
+        // liveness checks never reach the handler
+        let resp = if msg == PING {
+            PONG.to_string()
+        } else {
+            handler(msg).unwrap_or_else(|e| {
+                let resp = error_reply(&*e);
+                on_error(MonitorError::new(Phase::Handle, e));
+                resp
+            })
+        };
+        let res = match reply {
+            Reply::Close => s.write_all(resp.as_bytes()),
+            Reply::Line => s.write_all(format!("{}\n", resp).as_bytes()),
+            Reply::Framed(framing) => framing.write_frame(s, resp.as_bytes()),
+        };
+        res.map_err(|e| {
+            on_error(MonitorError::new(Phase::Write, e));
+        })
+    }
+
+    /// Set how often the client methods retry connecting
+    ///
+    /// With `retries` above zero, `send_string`, `send_bytes` and the
+    /// like use `connect_with_retry`, e.g. to wait for a server that
+    /// is still starting up. The default is a single attempt.
+    pub fn set_connect_retry(&mut self, retries: u32, interval: Duration) {
+        self.connect_retry = Some((retries, interval));
+    }
+
+    /// Set how often the client methods retry a whole exchange when
+    /// the connection breaks
+    ///
+    /// With `retries` above zero, `send_string`, `send_bytes`,
+    /// `send_bytes_binary` and `send_fd` connect, send and read the
+    /// response again, after `delay`, if that fails with
+    /// `BrokenPipe`, `ConnectionRefused` or `ConnectionReset`, e.g.
+    /// while the server restarts. The last error is returned once all
+    /// retries failed. A server that failed only after reading the
+    /// message may see it more than once. The default is no retries.
+    pub fn set_send_retry(&mut self, retries: u32, delay: Duration) {
+        self.send_retry = Some((retries, delay));
+    }
+
+    /// Run a client exchange using the send retry policy
+    fn with_send_retry<R, F>(&self, exchange: F) -> Result<R, std::io::Error>
+        where F: Fn() -> Result<R, std::io::Error>
+    {
+        use std::io::ErrorKind::{BrokenPipe, ConnectionRefused, ConnectionReset};
+
+        let (retries, delay) = self.send_retry.unwrap_or((0, Duration::ZERO));
+        let mut attempt = 0;
+        loop {
+            match exchange() {
+                Err(e) if attempt < retries && matches!(e.kind(), BrokenPipe | ConnectionRefused | ConnectionReset) => {
+                    attempt += 1;
+                    std::thread::sleep(delay);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Connect to the socket, retrying up to `retries` times every
+    /// `interval`
+    ///
+    /// Returns the connected stream, or the last error once all
+    /// retries have failed.
+    pub fn connect_with_retry(&self, retries: u32, interval: Duration) -> Result<UnixStream, std::io::Error> {
+        connect_with_retry(&self.sock, retries, interval)
+    }
+
+    /// Connect to the socket using the connect retry policy
+    fn connect(&self) -> Result<UnixStream, std::io::Error> {
+        let (retries, interval) = self.connect_retry.unwrap_or((0, Duration::ZERO));
+        self.connect_with_retry(retries, interval)
+    }
+
     /// Send a newline terminated string
     pub fn send_string(&self, msg: &str) -> Result<String, std::io::Error>{
-        let mut stream = UnixStream::connect(&self.sock)?;
+        self.with_send_retry(|| Self::exchange_string(self.connect()?, msg))
+    }
+
+    /// Send a newline terminated string on a connected stream and
+    /// read the response until the server closes it
+    pub(crate) fn exchange_string<S: Read + Write>(mut stream: S, msg: &str) -> Result<String, std::io::Error> {
         let mut buf = String::new();
 
         // send the message string
@@ -181,39 +1064,377 @@ impl SockMonitor {
         Ok(buf)
     }
 
-    /// Send a byte array
-    pub fn send_bytes(&self, msg: &[u8]) -> Result<String, std::io::Error>{
-        let mut stream = UnixStream::connect(&self.sock)?;
-        let mut buf = String::new();
+    /// Send a request on a connection kept open across calls
+    ///
+    /// Connects on first use and then reuses the connection for the
+    /// following requests; the server must be running
+    /// `serve_persistent`. The message is sent as a line and the
+    /// response line is returned with the newline stripped. If the
+    /// connection fails it is dropped, and the next request
+    /// reconnects.
+    pub fn request(&mut self, msg: &str) -> Result<String, std::io::Error> {
+        let res = self.try_request(msg);
+        if res.is_err() {
+            self.conn = None;
+        }
+        res
+    }
 
-        // find the length of message and create a byte
-        // array with it
-        let mut val = (msg.len() as u32).to_be_bytes().to_vec();
-        // append the message bytes to the byte array
-        val.append(&mut msg.to_vec());
+    /// Check the server on the connection held by `request` is alive
+    ///
+    /// Sends a `PING` line and returns the round trip time once the
+    /// server's `PONG` arrives; like `request` this connects first if
+    /// needed. Fails if the server is gone, or with `InvalidData` if
+    /// it answers anything else.
+    pub fn ping(&mut self) -> Result<Duration, std::io::Error> {
+        let start = Instant::now();
+        let resp = self.request(PING)?;
+        if resp != PONG {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("unexpected ping response {:?}", resp)));
+        }
+        Ok(start.elapsed())
+    }
 
-        // send the byte array
-        stream.write_all(&val)?;
-        // wait for response
-        stream.read_to_string(&mut buf)?;
-        // return response
-        Ok(buf)
+    /// Close the connection held by `request`
+    pub fn disconnect(&mut self) {
+        self.conn = None;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::{fs, thread, time};
+    fn try_request(&mut self, msg: &str) -> Result<String, std::io::Error> {
+        if self.conn.is_none() {
+            self.conn = Some(BufReader::new(self.connect()?));
+        }
+        Self::exchange_line(self.conn.as_mut().unwrap(), msg)
+    }
 
-    #[test]
-    fn test_mon_string() {
-        if fs::metadata("/tmp/mon-line.sock").is_ok() {
-            fs::remove_file("/tmp/mon-line.sock").unwrap();
+    /// Send a line on a kept open connection and read the response
+    /// line
+    pub(crate) fn exchange_line(conn: &mut BufReader<UnixStream>, msg: &str) -> Result<String, std::io::Error> {
+        let msg = msg.strip_suffix('\n').unwrap_or(msg);
+        conn.get_mut().write_all(format!("{}\n", msg).as_bytes())?;
+
+        let mut resp = String::new();
+        if conn.read_line(&mut resp)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
         }
+        if resp.ends_with('\n') {
+            resp.pop();
+        }
+        Ok(resp)
+    }
 
-        thread::spawn(|| {
-            let mon = SockMonitor::new("/tmp/mon-line.sock");
+    /// Send a byte array on a connection kept open across calls
+    ///
+    /// Like `request`, but the message is sent with its length
+    /// prepended and exactly one length prepended response is read,
+    /// so the server must be running `serve_persistent` with a byte
+    /// reader and `set_framed_responses`.
+    pub fn request_bytes(&mut self, msg: &[u8]) -> Result<String, std::io::Error> {
+        let res = self.try_request_bytes(msg);
+        if res.is_err() {
+            self.conn = None;
+        }
+        res
+    }
+
+    fn try_request_bytes(&mut self, msg: &[u8]) -> Result<String, std::io::Error> {
+        if self.conn.is_none() {
+            self.conn = Some(BufReader::new(self.connect()?));
+        }
+        let conn = self.conn.as_mut().unwrap();
+        self.framing.write_frame(conn.get_mut(), msg)?;
+        let resp = self.framing.read_frame(conn, self.max_message_len)?;
+        String::from_utf8(resp).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Send a byte array as a single datagram to a server running
+    /// `serve_datagram`
+    pub fn send_datagram(&self, msg: &[u8]) -> Result<(), std::io::Error> {
+        send_datagram(&self.sock, msg)
+    }
+
+    /// Send a byte array along with a file descriptor to a server
+    /// running `serve_fd`, and read the response
+    ///
+    /// The server gets its own duplicate of `fd`, so the caller still
+    /// owns `fd` and is free to close it once this returns.
+    pub fn send_fd(&self, fd: RawFd, msg: &[u8]) -> Result<String, std::io::Error> {
+        self.with_send_retry(|| {
+            let mut stream = self.connect()?;
+            let mut buf = String::new();
+
+            // send the byte array with the descriptor
+            fdpass::send_fd(&mut stream, fd, msg)?;
+            // wait for response
+            stream.read_to_string(&mut buf)?;
+            Ok(buf)
+        })
+    }
+
+    /// Send a byte array
+    pub fn send_bytes(&self, msg: &[u8]) -> Result<String, std::io::Error>{
+        self.with_send_retry(|| Self::exchange_bytes(self.connect()?, self.framing, msg))
+    }
+
+    /// Send a byte array and read a length prepended binary response
+    ///
+    /// The counterpart of `serve_binary`; responses longer than
+    /// `max_message_len` are refused. Returns the payload of the
+    /// response; a handler error comes back as an error wrapping the
+    /// `ServerError`, see `parse_binary_reply`.
+    pub fn send_bytes_binary(&self, msg: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        let resp = self.with_send_retry(|| {
+            let mut stream = self.connect()?;
+            self.framing.write_frame(&mut stream, msg)?;
+            self.framing.read_frame(&mut stream, self.max_message_len)
+        })?;
+        Self::parse_binary_reply(&resp)
+    }
+
+    /// Send a length prepended byte array on a connected stream and
+    /// read the response until the server closes it
+    pub(crate) fn exchange_bytes<S: Read + Write>(mut stream: S, framing: Framing, msg: &[u8]) -> Result<String, std::io::Error> {
+        let mut buf = String::new();
+
+        // send the byte array
+        framing.write_frame(&mut stream, msg)?;
+        // wait for response
+        stream.read_to_string(&mut buf)?;
+        // return response
+        Ok(buf)
+    }
+
+    /// Tell a regular response from a handler error
+    ///
+    /// Takes a response as returned by `send_string`, `send_bytes` or
+    /// `request`; an `ERR_PREFIX` response becomes a `ServerError`
+    /// carrying its message, any other response is returned as is.
+    pub fn parse_reply(resp: &str) -> Result<String, ServerError> {
+        match resp.strip_prefix(ERR_PREFIX) {
+            Some(msg) => Err(ServerError::new(msg.strip_suffix('\n').unwrap_or(msg))),
+            None => Ok(resp.to_string()),
+        }
+    }
+
+    /// Decode the status byte of a binary response frame
+    ///
+    /// The counterpart of `parse_reply` for frames written by
+    /// `serve_binary` and `serve_raw`: an `OK_FRAME` response gives
+    /// its payload, an `ERR_FRAME` one an error of kind `Other`
+    /// wrapping a `ServerError` with its message. Any other frame
+    /// fails with `InvalidData`.
+    pub fn parse_binary_reply(resp: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match resp.split_first() {
+            Some((&OK_FRAME, payload)) => Ok(payload.to_vec()),
+            Some((&ERR_FRAME, msg)) => Err(std::io::Error::other(ServerError::new(&String::from_utf8_lossy(msg)))),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed binary response")),
+        }
+    }
+}
+
+/// Response to a request the handler failed with `e`
+pub(crate) fn error_reply(e: &dyn Error) -> String {
+    format!("{}{}", ERR_PREFIX, e.to_string().replace('\n', " "))
+}
+
+/// How the server ends a response
+#[derive(Clone, Copy)]
+pub(crate) enum Reply {
+    /// Bare response, ended by closing the connection
+    Close,
+    /// Response followed by a newline
+    Line,
+    /// Response with its length prepended
+    Framed(Framing)
+}
+
+/// Connection of `serve_persistent`, as handed to its reader
+///
+/// The connection is buffered for its whole life, and a read never
+/// goes past the end of a line, so a reader buffering on its own,
+/// like `read_line`, cannot swallow the start of the next request.
+/// With an idle timeout set, all the reads of a request share one
+/// deadline, so the request has to be complete in time however its
+/// bytes trickle in; see `SockMonitor::set_idle_timeout`.
+pub struct PersistentConn {
+    reader: BufReader<Deadline>
+}
+
+impl PersistentConn {
+    /// The underlying connection, e.g. to check the peer
+    pub fn get_ref(&self) -> &UnixStream {
+        &self.reader.get_ref().stream
+    }
+}
+
+impl Read for PersistentConn {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let avail = self.reader.fill_buf()?;
+        let line = avail.iter().position(|&b| b == b'\n').map_or(avail.len(), |i| i + 1);
+        let n = line.min(buf.len());
+        buf[..n].copy_from_slice(&avail[..n]);
+        self.reader.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for PersistentConn {
+    fn fill_buf(&mut self) -> Result<&[u8], std::io::Error> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+/// Stream whose reads fail once the deadline has passed
+struct Deadline {
+    stream: UnixStream,
+    deadline: Option<Instant>
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(left))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+/// Connections held open by `serve_persistent`, so stopping the
+/// server can close them
+#[derive(Default)]
+struct Connections {
+    next: AtomicU64,
+    open: Mutex<HashMap<u64, UnixStream>>
+}
+
+impl Connections {
+    /// Keep a handle on an accepted connection until it is removed
+    fn add(&self, s: &UnixStream) -> Result<u64, std::io::Error> {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let s = s.try_clone()?;
+        self.open.lock().unwrap_or_else(|e| e.into_inner()).insert(id, s);
+        Ok(id)
+    }
+
+    fn remove(&self, id: u64) {
+        self.open.lock().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    /// Shut down the reading side of every open connection
+    ///
+    /// A blocked read sees the end of the stream, so the connection
+    /// is closed once its current request, if any, is answered.
+    fn close_all(&self) {
+        for s in self.open.lock().unwrap_or_else(|e| e.into_inner()).values() {
+            let _ = s.shutdown(Shutdown::Read);
+        }
+    }
+}
+
+/// Removes the socket file of a serving monitor when dropped
+///
+/// Held for as long as serving goes on, so the file is cleaned up on
+/// every way out, including errors and a panicking handler, rather
+/// than left behind for the next start.
+pub(crate) struct SocketFile<'a>(&'a SockMonitor);
+
+impl Drop for SocketFile<'_> {
+    fn drop(&mut self) {
+        self.0.remove_socket();
+    }
+}
+
+/// Exponential backoff between consecutive accept errors
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Option<Duration>
+}
+
+impl Backoff {
+    fn new(initial: Duration, max: Duration) -> Self {
+        Backoff { initial, max, current: None }
+    }
+
+    /// How long to sleep after another error
+    pub(crate) fn next(&mut self) -> Duration {
+        let next = match self.current {
+            Some(d) => (d * 2).min(self.max),
+            None => self.initial.min(self.max),
+        };
+        self.current = Some(next);
+        next
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Whether the socket is in the Linux abstract namespace
+fn is_abstract(sock: &str) -> bool {
+    cfg!(target_os = "linux") && sock.starts_with('@')
+}
+
+/// Connect to a named socket, or an abstract one on Linux
+pub(crate) fn connect(sock: &str) -> Result<UnixStream, std::io::Error> {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = sock.strip_prefix('@') {
+        return UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?);
+    }
+    UnixStream::connect(sock)
+}
+
+/// Connect to a socket, retrying up to `retries` times every
+/// `interval`; see `SockMonitor::connect_with_retry`
+pub(crate) fn connect_with_retry(sock: &str, retries: u32, interval: Duration) -> Result<UnixStream, std::io::Error> {
+    let mut attempt = 0;
+    loop {
+        match connect(sock) {
+            Err(_) if attempt < retries => {
+                attempt += 1;
+                std::thread::sleep(interval);
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Send a datagram from an unbound socket
+fn send_datagram(sock: &str, msg: &[u8]) -> Result<(), std::io::Error> {
+    let dgram = UnixDatagram::unbound()?;
+    #[cfg(target_os = "linux")]
+    if let Some(name) = sock.strip_prefix('@') {
+        dgram.send_to_addr(msg, &SocketAddr::from_abstract_name(name)?)?;
+        return Ok(());
+    }
+    dgram.send_to(msg, sock)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::{Endian, PrefixWidth};
+    use std::{fs, thread, time};
+
+    #[test]
+    fn test_mon_string() {
+        if fs::metadata("/tmp/mon-line.sock").is_ok() {
+            fs::remove_file("/tmp/mon-line.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-line.sock");
             mon.serve(SockMonitor::read_line, move |req| {
                 println!("{}", req);
                 assert_eq!(req, "the quick brown fox jumps over the lazy dog");
@@ -221,7 +1442,7 @@ mod tests {
             }).unwrap();
         });
 
-        while !fs::metadata("/tmp/mon-line.sock").is_ok() {
+        while fs::metadata("/tmp/mon-line.sock").is_err() {
             thread::sleep(time::Duration::from_millis(500));
         }        
         let client = SockMonitor::new("/tmp/mon-line.sock");
@@ -247,7 +1468,7 @@ mod tests {
             }).unwrap();
         });
 
-        while !fs::metadata("/tmp/mon-bytes.sock").is_ok() {
+        while fs::metadata("/tmp/mon-bytes.sock").is_err() {
             thread::sleep(time::Duration::from_millis(500));
         }        
         let client = SockMonitor::new("/tmp/mon-bytes.sock");
@@ -256,4 +1477,755 @@ mod tests {
         assert!(resp.is_ok());
         assert_eq!(resp.unwrap(), "OK");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mon_concurrent() {
+        if fs::metadata("/tmp/mon-concurrent.sock").is_ok() {
+            fs::remove_file("/tmp/mon-concurrent.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-concurrent.sock");
+            mon.serve_concurrent(SockMonitor::read_line, move |req| {
+                Ok(format!("OK {}", req))
+            }, 2).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-concurrent.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        // a client that never sends must not block the others
+        let _stalled = UnixStream::connect("/tmp/mon-concurrent.sock").unwrap();
+        let client = SockMonitor::new("/tmp/mon-concurrent.sock");
+        for i in 0..3 {
+            let resp = client.send_string(&format!("{}", i));
+            assert_eq!(resp.unwrap(), format!("OK {}", i));
+        }
+    }
+
+    #[test]
+    fn test_mon_max_len() {
+        if fs::metadata("/tmp/mon-maxlen.sock").is_ok() {
+            fs::remove_file("/tmp/mon-maxlen.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mut mon = SockMonitor::new("/tmp/mon-maxlen.sock");
+            mon.set_max_message_len(64);
+            let reader = mon.bytes_reader();
+            mon.serve(reader, move |_| Ok("OK".to_string())).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-maxlen.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        // oversized prefixes are dropped without a response
+        for len in [65u32, u32::MAX] {
+            let mut stream = UnixStream::connect("/tmp/mon-maxlen.sock").unwrap();
+            stream.write_all(&len.to_be_bytes()).unwrap();
+            let mut buf = String::new();
+            stream.read_to_string(&mut buf).unwrap();
+            assert_eq!(buf, "");
+        }
+        let client = SockMonitor::new("/tmp/mon-maxlen.sock");
+        let resp = client.send_bytes(&[b'a'; 64]);
+        assert_eq!(resp.unwrap(), "OK");
+    }
+
+    #[test]
+    fn test_mon_max_line_len() {
+        if fs::metadata("/tmp/mon-maxline.sock").is_ok() {
+            fs::remove_file("/tmp/mon-maxline.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let mut mon = SockMonitor::new("/tmp/mon-maxline.sock");
+            mon.set_max_line_len(16);
+            let tx = std::sync::Mutex::new(tx);
+            mon.on_error(move |e| tx.lock().unwrap().send(e.error().to_string()).unwrap());
+            let reader = mon.line_reader();
+            mon.serve(reader, Ok).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-maxline.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-maxline.sock");
+        assert_eq!(client.send_string(&"a".repeat(16)).unwrap(), "a".repeat(16));
+        // a long line with no newline is cut off at the limit
+        let mut stream = UnixStream::connect("/tmp/mon-maxline.sock").unwrap();
+        let _ = stream.write_all(&[b'a'; 4096]);
+        let mut buf = String::new();
+        let _ = stream.read_to_string(&mut buf);
+        assert_eq!(buf, "");
+        assert_eq!(rx.recv().unwrap(), "line exceeds 16 bytes");
+
+        // an endless stream without newline is rejected as well
+        let err = SockMonitor::read_line(&mut std::io::repeat(b'a')).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_mon_shutdown() {
+        if fs::metadata("/tmp/mon-shutdown.sock").is_ok() {
+            fs::remove_file("/tmp/mon-shutdown.sock").unwrap();
+        }
+
+        let mon = SockMonitor::new("/tmp/mon-shutdown.sock");
+        let handle = mon.shutdown_handle();
+        let server = thread::spawn(move || {
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string()))
+        });
+
+        while fs::metadata("/tmp/mon-shutdown.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-shutdown.sock");
+        assert_eq!(client.send_string("hello").unwrap(), "OK");
+        handle.stop();
+        assert!(server.join().unwrap().is_ok());
+        assert!(fs::metadata("/tmp/mon-shutdown.sock").is_err());
+    }
+
+    #[test]
+    fn test_mon_cleanup_on_panic() {
+        if fs::metadata("/tmp/mon-panic.sock").is_ok() {
+            fs::remove_file("/tmp/mon-panic.sock").unwrap();
+        }
+
+        let server = thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-panic.sock");
+            mon.serve(SockMonitor::read_line, move |_| panic!("handler failed"))
+        });
+
+        while fs::metadata("/tmp/mon-panic.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-panic.sock");
+        assert!(client.send_string("hello").map_or(true, |r| r.is_empty()));
+        assert!(server.join().is_err());
+        // the socket file went away with the unwinding server
+        assert!(fs::metadata("/tmp/mon-panic.sock").is_err());
+    }
+
+    #[test]
+    fn test_mon_timeout() {
+        if fs::metadata("/tmp/mon-timeout.sock").is_ok() {
+            fs::remove_file("/tmp/mon-timeout.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mut mon = SockMonitor::new("/tmp/mon-timeout.sock");
+            mon.set_read_timeout(Some(time::Duration::from_millis(100)));
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string())).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-timeout.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        // the stalled client is dropped once its read times out
+        let _stalled = UnixStream::connect("/tmp/mon-timeout.sock").unwrap();
+        let client = SockMonitor::new("/tmp/mon-timeout.sock");
+        assert_eq!(client.send_string("hello").unwrap(), "OK");
+    }
+
+    #[test]
+    fn test_mon_persistent() {
+        if fs::metadata("/tmp/mon-persistent.sock").is_ok() {
+            fs::remove_file("/tmp/mon-persistent.sock").unwrap();
+        }
+
+        let mon = SockMonitor::new("/tmp/mon-persistent.sock");
+        let handle = mon.shutdown_handle();
+        let server = thread::spawn(move || {
+            mon.serve_persistent(SockMonitor::read_line, move |req| {
+                Ok(format!("OK {}", req))
+            }, 2).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-persistent.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let mut client = SockMonitor::new("/tmp/mon-persistent.sock");
+        let mut other = SockMonitor::new("/tmp/mon-persistent.sock");
+        for i in 0..3 {
+            assert_eq!(client.request(&format!("{}", i)).unwrap(), format!("OK {}", i));
+            assert_eq!(other.request("x\n").unwrap(), "OK x");
+        }
+        client.disconnect();
+        other.disconnect();
+        // requests sent back to back are all answered
+        let mut stream = UnixStream::connect("/tmp/mon-persistent.sock").unwrap();
+        stream.write_all(b"a\nb\n").unwrap();
+        stream.set_read_timeout(Some(time::Duration::from_secs(5))).unwrap();
+        let mut conn = BufReader::new(stream);
+        for req in ["a", "b"] {
+            let mut resp = String::new();
+            conn.read_line(&mut resp).unwrap();
+            assert_eq!(resp, format!("OK {}\n", req));
+        }
+        drop(conn);
+        handle.stop();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_mon_persistent_stop() {
+        if fs::metadata("/tmp/mon-persistent-stop.sock").is_ok() {
+            fs::remove_file("/tmp/mon-persistent-stop.sock").unwrap();
+        }
+
+        let mon = SockMonitor::new("/tmp/mon-persistent-stop.sock");
+        let handle = mon.shutdown_handle();
+        let server = thread::spawn(move || {
+            mon.serve_persistent(SockMonitor::read_line, move |req| {
+                Ok(format!("OK {}", req))
+            }, 2).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-persistent-stop.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let mut client = SockMonitor::new("/tmp/mon-persistent-stop.sock");
+        assert_eq!(client.request("a").unwrap(), "OK a");
+        // stopping does not wait for the connected client to leave
+        handle.stop();
+        server.join().unwrap();
+        assert!(client.request("b").is_err());
+    }
+
+    #[test]
+    fn test_mon_persistent_flow() {
+        if fs::metadata("/tmp/mon-flow.sock").is_ok() {
+            fs::remove_file("/tmp/mon-flow.sock").unwrap();
+        }
+
+        thread::spawn(move || {
+            let mon = SockMonitor::new("/tmp/mon-flow.sock");
+            mon.serve_persistent_flow(SockMonitor::read_line, move |req| {
+                Ok(match req.as_str() {
+                    "quit" => ControlFlow::Break("BYE".to_string()),
+                    _ => ControlFlow::Continue(format!("OK {}", req)),
+                })
+            }, 1).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-flow.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let stream = UnixStream::connect("/tmp/mon-flow.sock").unwrap();
+        let mut conn = BufReader::new(stream);
+        assert_eq!(SockMonitor::exchange_line(&mut conn, "a").unwrap(), "OK a");
+        assert_eq!(SockMonitor::exchange_line(&mut conn, "quit").unwrap(), "BYE");
+        // the server closed the connection
+        let mut rest = String::new();
+        assert_eq!(conn.read_line(&mut rest).unwrap(), 0);
+
+        // the worker is free again for the next client
+        let mut client = SockMonitor::new("/tmp/mon-flow.sock");
+        assert_eq!(client.request("b").unwrap(), "OK b");
+    }
+
+    #[test]
+    fn test_mon_ping() {
+        if fs::metadata("/tmp/mon-ping.sock").is_ok() {
+            fs::remove_file("/tmp/mon-ping.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let mon = SockMonitor::new("/tmp/mon-ping.sock");
+            let tx = std::sync::Mutex::new(tx);
+            mon.serve_persistent(SockMonitor::read_line, move |req| {
+                tx.lock().unwrap().send(req.clone()).unwrap();
+                Ok(format!("OK {}", req))
+            }, 1).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-ping.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let mut client = SockMonitor::new("/tmp/mon-ping.sock");
+        assert!(client.ping().is_ok());
+        assert_eq!(client.request("hello").unwrap(), "OK hello");
+        assert!(client.ping().unwrap() < time::Duration::from_secs(1));
+        // the handler never saw the pings
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), ["hello"]);
+
+        // a server that is not there fails the ping
+        let mut client = SockMonitor::new("/tmp/mon-ping-none.sock");
+        assert!(client.ping().is_err());
+    }
+
+    #[test]
+    fn test_mon_binary() {
+        if fs::metadata("/tmp/mon-binary.sock").is_ok() {
+            fs::remove_file("/tmp/mon-binary.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-binary.sock");
+            mon.serve_binary(SockMonitor::read_bytes, move |req| {
+                match req.as_str() {
+                    "get" => Ok(vec![0xFF, 0x00, 0x7F]),
+                    "nak" => Ok(vec![ERR_FRAME, 0x01]),
+                    _ => Err("unknown request".into()),
+                }
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-binary.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-binary.sock");
+        assert_eq!(client.send_bytes_binary(b"get").unwrap(), vec![0xFF, 0x00, 0x7F]);
+        // a payload may start with the error status byte
+        assert_eq!(client.send_bytes_binary(b"nak").unwrap(), vec![ERR_FRAME, 0x01]);
+        let err = client.send_bytes_binary(b"put").unwrap_err();
+        let err = err.get_ref().and_then(|e| e.downcast_ref::<ServerError>()).unwrap();
+        assert_eq!(err.message(), "unknown request");
+        assert_eq!(SockMonitor::parse_binary_reply(&[OK_FRAME, 0xFF]).unwrap(), vec![0xFF]);
+        assert!(SockMonitor::parse_binary_reply(&[]).is_err());
+    }
+
+    #[test]
+    fn test_mon_raw() {
+        if fs::metadata("/tmp/mon-raw.sock").is_ok() {
+            fs::remove_file("/tmp/mon-raw.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-raw.sock");
+            mon.serve_raw(move |mut req| {
+                req.reverse();
+                Ok(req)
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-raw.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-raw.sock");
+        // not valid UTF-8
+        let resp = client.send_bytes_binary(&[0xFF, 0x00, 0xC0]);
+        assert_eq!(resp.unwrap(), vec![0xC0, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_mon_mode() {
+        if fs::metadata("/tmp/mon-mode.sock").is_ok() {
+            fs::remove_file("/tmp/mon-mode.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-mode.sock").with_mode(0o600);
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string())).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-mode.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-mode.sock");
+        assert_eq!(client.send_string("hello").unwrap(), "OK");
+        let mode = fs::metadata("/tmp/mon-mode.sock").unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_mon_backlog() {
+        if fs::metadata("/tmp/mon-backlog.sock").is_ok() {
+            fs::remove_file("/tmp/mon-backlog.sock").unwrap();
+        }
+
+        assert_eq!(SockMonitor::new("/tmp/mon-backlog.sock").backlog(), None);
+        let mon = SockMonitor::new("/tmp/mon-backlog.sock").with_backlog(u32::MAX);
+        assert_eq!(mon.backlog(), Some(libc::SOMAXCONN as u32));
+
+        // clients queue up while the handler is busy
+        let mon = mon.with_backlog(16);
+        let handle = mon.shutdown_handle();
+        let server = thread::spawn(move || {
+            mon.serve(SockMonitor::read_line, move |req| {
+                thread::sleep(time::Duration::from_millis(10));
+                Ok(req)
+            })
+        });
+
+        while fs::metadata("/tmp/mon-backlog.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let clients: Vec<_> = (0..8).map(|i| thread::spawn(move || {
+            let client = SockMonitor::new("/tmp/mon-backlog.sock");
+            assert_eq!(client.send_string(&format!("{}", i)).unwrap(), format!("{}", i));
+        })).collect();
+        for c in clients {
+            c.join().unwrap();
+        }
+        handle.stop();
+        server.join().unwrap().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mon_abstract() {
+        thread::spawn(|| {
+            let mon = SockMonitor::new("@mon-abstract");
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string())).unwrap();
+        });
+
+        let client = SockMonitor::new("@mon-abstract");
+        let mut resp = client.send_string("hello");
+        while resp.is_err() {
+            thread::sleep(time::Duration::from_millis(100));
+            resp = client.send_string("hello");
+        }
+        assert_eq!(resp.unwrap(), "OK");
+        assert!(fs::metadata("@mon-abstract").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mon_cred() {
+        if fs::metadata("/tmp/mon-cred.sock").is_ok() {
+            fs::remove_file("/tmp/mon-cred.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-cred.sock");
+            mon.serve_with_cred(SockMonitor::read_line, move |cred, _| {
+                Ok(format!("{} {}", cred.pid, cred.uid))
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-cred.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-cred.sock");
+        // SAFETY: getuid has no preconditions
+        let uid = unsafe { libc::getuid() };
+        assert_eq!(client.send_string("hello").unwrap(), format!("{} {}", std::process::id(), uid));
+    }
+
+    #[test]
+    fn test_mon_framing() {
+        if fs::metadata("/tmp/mon-framing.sock").is_ok() {
+            fs::remove_file("/tmp/mon-framing.sock").unwrap();
+        }
+
+        let framing = Framing::new(PrefixWidth::U16, Endian::Little);
+        thread::spawn(move || {
+            let mut mon = SockMonitor::new("/tmp/mon-framing.sock");
+            mon.set_framing(framing);
+            let reader = mon.bytes_reader();
+            mon.serve(reader, move |req| Ok(format!("OK {}", req))).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-framing.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let mut client = SockMonitor::new("/tmp/mon-framing.sock");
+        client.set_framing(framing);
+        assert_eq!(client.send_bytes(b"hello").unwrap(), "OK hello");
+        let mut stream = UnixStream::connect("/tmp/mon-framing.sock").unwrap();
+        stream.write_all(&[5, 0]).unwrap();
+        stream.write_all(b"world").unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "OK world");
+    }
+
+    #[test]
+    fn test_mon_connect_retry() {
+        if fs::metadata("/tmp/mon-retry.sock").is_ok() {
+            fs::remove_file("/tmp/mon-retry.sock").unwrap();
+        }
+
+        let client = SockMonitor::new("/tmp/mon-retry.sock");
+        assert!(client.connect_with_retry(2, time::Duration::from_millis(10)).is_err());
+
+        thread::spawn(|| {
+            thread::sleep(time::Duration::from_millis(200));
+            let mon = SockMonitor::new("/tmp/mon-retry.sock");
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string())).unwrap();
+        });
+        let mut client = SockMonitor::new("/tmp/mon-retry.sock");
+        client.set_connect_retry(100, time::Duration::from_millis(50));
+        assert_eq!(client.send_string("hello").unwrap(), "OK");
+    }
+
+    #[test]
+    fn test_mon_send_retry() {
+        if fs::metadata("/tmp/mon-send-retry.sock").is_ok() {
+            fs::remove_file("/tmp/mon-send-retry.sock").unwrap();
+        }
+        // a socket file nobody listens on refuses connections, as
+        // while a server restarts
+        drop(UnixListener::bind("/tmp/mon-send-retry.sock").unwrap());
+
+        let client = SockMonitor::new("/tmp/mon-send-retry.sock");
+        let err = client.send_string("hello").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+
+        thread::spawn(|| {
+            thread::sleep(time::Duration::from_millis(200));
+            let mon = SockMonitor::new("/tmp/mon-send-retry.sock");
+            mon.serve(SockMonitor::read_line, move |_| Ok("OK".to_string())).unwrap();
+        });
+        let mut client = SockMonitor::new("/tmp/mon-send-retry.sock");
+        client.set_send_retry(2, time::Duration::from_millis(10));
+        // too few retries to outlast the restart
+        assert!(client.send_string("hello").is_err());
+        client.set_send_retry(100, time::Duration::from_millis(50));
+        assert_eq!(client.send_string("hello").unwrap(), "OK");
+    }
+
+    #[test]
+    fn test_mon_idle_timeout() {
+        if fs::metadata("/tmp/mon-idle.sock").is_ok() {
+            fs::remove_file("/tmp/mon-idle.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        thread::spawn(move || {
+            let mut mon = SockMonitor::new("/tmp/mon-idle.sock");
+            mon.set_idle_timeout(time::Duration::from_millis(200));
+            mon.on_error(move |e| tx.lock().unwrap().send(e.phase()).unwrap());
+            mon.serve_persistent(SockMonitor::read_line, move |req| {
+                Ok(format!("OK {}", req))
+            }, 1).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-idle.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let stream = UnixStream::connect("/tmp/mon-idle.sock").unwrap();
+        let mut conn = BufReader::new(stream);
+        assert_eq!(SockMonitor::exchange_line(&mut conn, "a").unwrap(), "OK a");
+        // a silent client gets disconnected
+        let mut rest = String::new();
+        assert_eq!(conn.read_line(&mut rest).unwrap(), 0);
+        assert_eq!(rx.recv().unwrap(), Phase::Idle);
+
+        // and so does one stalling in the middle of a request
+        let mut stream = UnixStream::connect("/tmp/mon-idle.sock").unwrap();
+        stream.write_all(b"par").unwrap();
+        assert_eq!(stream.read_to_string(&mut rest).unwrap(), 0);
+        assert_eq!(rx.recv().unwrap(), Phase::Idle);
+
+        // or one trickling in a request, however short the gaps
+        let mut stream = UnixStream::connect("/tmp/mon-idle.sock").unwrap();
+        for _ in 0..10 {
+            let _ = stream.write_all(b"x");
+            thread::sleep(time::Duration::from_millis(80));
+        }
+        assert_eq!(rx.try_recv().unwrap(), Phase::Idle);
+        assert_eq!(stream.read_to_string(&mut rest).unwrap(), 0);
+
+        // a client leaving on its own is not reported
+        let mut client = SockMonitor::new("/tmp/mon-idle.sock");
+        assert_eq!(client.request("b").unwrap(), "OK b");
+        client.disconnect();
+        assert!(rx.recv_timeout(time::Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_mon_on_error() {
+        if fs::metadata("/tmp/mon-error.sock").is_ok() {
+            fs::remove_file("/tmp/mon-error.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        thread::spawn(move || {
+            let mut mon = SockMonitor::new("/tmp/mon-error.sock");
+            mon.on_error(move |e| tx.lock().unwrap().send((e.phase(), e.to_string())).unwrap());
+            mon.serve(SockMonitor::read_bytes, move |req| match req.as_str() {
+                "ok" => Ok("OK".to_string()),
+                _ => Err("bad request".into()),
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-error.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-error.sock");
+        assert_eq!(client.send_bytes(b"ok").unwrap(), "OK");
+        let resp = client.send_bytes(b"nok").unwrap();
+        assert_eq!(resp, "ERR: bad request");
+        assert_eq!(SockMonitor::parse_reply(&resp).unwrap_err().message(), "bad request");
+        assert_eq!(SockMonitor::parse_reply("OK"), Ok("OK".to_string()));
+        assert_eq!(rx.recv().unwrap(), (Phase::Handle, "handle bad request".to_string()));
+        assert_eq!(client.send_bytes(&[0xFF]).unwrap(), "");
+        assert_eq!(rx.recv().unwrap().0, Phase::Read);
+    }
+
+    #[test]
+    fn test_mon_framed_responses() {
+        if fs::metadata("/tmp/mon-framed.sock").is_ok() {
+            fs::remove_file("/tmp/mon-framed.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mut mon = SockMonitor::new("/tmp/mon-framed.sock");
+            mon.set_framed_responses(true);
+            mon.serve_persistent(SockMonitor::read_bytes, move |req| {
+                Ok(format!("OK\n{}", req))
+            }, 1).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-framed.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let mut client = SockMonitor::new("/tmp/mon-framed.sock");
+        for i in 0..3 {
+            // newlines in the response are fine with framing
+            assert_eq!(client.request_bytes(format!("{}", i).as_bytes()).unwrap(), format!("OK\n{}", i));
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let ms = time::Duration::from_millis;
+        let mut backoff = Backoff::new(ms(10), ms(50));
+        assert_eq!(backoff.next(), ms(10));
+        assert_eq!(backoff.next(), ms(20));
+        assert_eq!(backoff.next(), ms(40));
+        assert_eq!(backoff.next(), ms(50));
+        assert_eq!(backoff.next(), ms(50));
+        backoff.reset();
+        assert_eq!(backoff.next(), ms(10));
+    }
+
+    #[test]
+    fn test_mon_datagram() {
+        if fs::metadata("/tmp/mon-dgram.sock").is_ok() {
+            fs::remove_file("/tmp/mon-dgram.sock").unwrap();
+        }
+        if fs::metadata("/tmp/mon-dgram-client.sock").is_ok() {
+            fs::remove_file("/tmp/mon-dgram-client.sock").unwrap();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mon = SockMonitor::new("/tmp/mon-dgram.sock");
+        let handle = mon.shutdown_handle();
+        let server = thread::spawn(move || {
+            mon.serve_datagram(move |msg, addr| {
+                // answer senders that can be answered
+                if let Some(path) = addr.as_ref().and_then(|a| a.as_pathname()) {
+                    UnixDatagram::unbound().unwrap().send_to(b"OK", path).unwrap();
+                }
+                tx.send((msg, addr.is_some())).unwrap();
+            })
+        });
+
+        while fs::metadata("/tmp/mon-dgram.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-dgram.sock");
+        client.send_datagram(&[0xFF, 0x00]).unwrap();
+        assert_eq!(rx.recv().unwrap(), (vec![0xFF, 0x00], false));
+
+        let bound = UnixDatagram::bind("/tmp/mon-dgram-client.sock").unwrap();
+        bound.send_to(b"ping", "/tmp/mon-dgram.sock").unwrap();
+        assert_eq!(rx.recv().unwrap(), (b"ping".to_vec(), true));
+        let mut buf = [0; 8];
+        let len = bound.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"OK");
+
+        handle.stop();
+        server.join().unwrap().unwrap();
+        assert!(fs::metadata("/tmp/mon-dgram.sock").is_err());
+        fs::remove_file("/tmp/mon-dgram-client.sock").unwrap();
+    }
+
+    #[test]
+    fn test_mon_ctx() {
+        if fs::metadata("/tmp/mon-ctx.sock").is_ok() {
+            fs::remove_file("/tmp/mon-ctx.sock").unwrap();
+        }
+
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let ctx = Arc::clone(&count);
+        thread::spawn(move || {
+            let mon = SockMonitor::new("/tmp/mon-ctx.sock");
+            mon.serve_with_ctx(ctx, SockMonitor::read_line, move |count, _| {
+                Ok(format!("{}", count.fetch_add(1, Ordering::SeqCst) + 1))
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-ctx.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-ctx.sock");
+        assert_eq!(client.send_string("a").unwrap(), "1");
+        assert_eq!(client.send_string("b").unwrap(), "2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_mon_fd() {
+        if fs::metadata("/tmp/mon-fd.sock").is_ok() {
+            fs::remove_file("/tmp/mon-fd.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-fd.sock");
+            mon.serve_fd(move |fd, req| {
+                let mut file = fs::File::from(fd);
+                let mut buf = String::new();
+                file.read_to_string(&mut buf)?;
+                Ok(format!("{} {}", String::from_utf8(req)?, buf))
+            }).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-fd.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        fs::write("/tmp/mon-fd.txt", "hello").unwrap();
+        let file = fs::File::open("/tmp/mon-fd.txt").unwrap();
+        let client = SockMonitor::new("/tmp/mon-fd.sock");
+        assert_eq!(client.send_fd(file.as_raw_fd(), b"file").unwrap(), "file hello");
+        // a request without a descriptor is refused
+        assert!(!client.send_bytes(b"file").unwrap_or_default().starts_with("file"));
+        drop(file);
+        fs::remove_file("/tmp/mon-fd.txt").unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_mon_json() {
+        #[derive(serde::Deserialize)]
+        struct Req {
+            a: i32,
+            b: i32
+        }
+        #[derive(serde::Serialize)]
+        struct Resp {
+            sum: i32
+        }
+
+        if fs::metadata("/tmp/mon-json.sock").is_ok() {
+            fs::remove_file("/tmp/mon-json.sock").unwrap();
+        }
+
+        thread::spawn(|| {
+            let mon = SockMonitor::new("/tmp/mon-json.sock");
+            mon.serve_json(move |req: Req| Ok(Resp { sum: req.a + req.b })).unwrap();
+        });
+
+        while fs::metadata("/tmp/mon-json.sock").is_err() {
+            thread::sleep(time::Duration::from_millis(500));
+        }
+        let client = SockMonitor::new("/tmp/mon-json.sock");
+        assert_eq!(client.send_string(r#"{"a": 1, "b": 2}"#).unwrap(), "{\"sum\":3}\n");
+        let resp = client.send_string(r#"{"a": 1}"#).unwrap();
+        assert!(resp.starts_with("ERR: missing field `b`"));
+        assert!(SockMonitor::parse_reply(&resp).is_err());
+
+        let mut line = "[1, 2]\n".as_bytes();
+        let v: Vec<u32> = SockMonitor::read_json(&mut line).unwrap();
+        assert_eq!(v, [1, 2]);
+    }
+}
@@ -0,0 +1,147 @@
+use std::net::{TcpListener, TcpStream};
+use std::error::Error;
+use std::sync::Arc;
+use asyncworkers::Workers;
+use crate::error::{self, ErrorHook, MonitorError, Phase};
+use crate::framing::Framing;
+use crate::sockmonitor::{Reply, SockMonitor};
+
+/// Generic TCP Socket Monitor
+///
+/// The `SockMonitor` protocol over TCP, for monitors that have to be
+/// reached from other machines. The readers of `SockMonitor`, like
+/// `SockMonitor::read_line` and `SockMonitor::read_bytes`, work for
+/// both.
+///
+/// ```
+/// use unixsockmon::{SockMonitor, TcpMonitor};
+/// use std::{thread, time};
+///
+/// //the reciever
+/// thread::spawn(|| {
+///     let mon = TcpMonitor::new("127.0.0.1:47301");
+///     mon.serve(SockMonitor::read_line, move |req| {
+///         println!("{}", req);
+///         Ok("OK".to_string())
+///     }).unwrap();
+/// });
+///
+/// //the sender
+/// let client = TcpMonitor::new("127.0.0.1:47301");
+/// let mut resp = client.send_string("the quick brown fox jumps over the lazy dog");
+/// while resp.is_err() {
+///     thread::sleep(time::Duration::from_millis(100));
+///     resp = client.send_string("the quick brown fox jumps over the lazy dog");
+/// }
+/// assert_eq!(resp.unwrap(), "OK");
+/// ```
+///
+pub struct TcpMonitor {
+    addr: String,
+    on_error: ErrorHook
+}
+
+impl TcpMonitor {
+    /// Create a new TCP monitor for an address like `host:port`
+    pub fn new(addr: &str) -> Self {
+        TcpMonitor { addr: addr.to_string(), on_error: error::log_error() }
+    }
+
+    /// Set a hook to be called with the errors of a serving monitor;
+    /// see `SockMonitor::on_error`
+    pub fn on_error<F>(&mut self, hook: F)
+        where F: Fn(MonitorError) + Send + Sync + 'static
+    {
+        self.on_error = Arc::new(hook);
+    }
+
+    /// Serve the TCP address; see `SockMonitor::serve`
+    pub fn serve<H, R>(&self, reader: R, handler: H) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<String, Box<dyn Error>>,
+              H: Send + 'static,
+              R: Fn(&mut TcpStream) -> Result<String, std::io::Error>,
+              R: Send + 'static
+    {
+        let listener = TcpListener::bind(&self.addr)?;
+
+        // accept and process each connection
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => SockMonitor::handle(s, &reader, &handler, Reply::Close, &*self.on_error),
+                Err(e) => (self.on_error)(MonitorError::new(Phase::Accept, e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Serve the TCP address, handling up to `workers` connections
+    /// in parallel; see `SockMonitor::serve_concurrent`
+    pub fn serve_concurrent<H, R>(&self, reader: R, handler: H, workers: usize) -> Result<(), std::io::Error>
+        where H: Fn(String) -> Result<String, Box<dyn Error>>,
+              H: Send + Sync + 'static,
+              R: Fn(&mut TcpStream) -> Result<String, std::io::Error>,
+              R: Send + Sync + 'static
+    {
+        let listener = TcpListener::bind(&self.addr)?;
+        let pool = Workers::try_new(workers)?;
+        let reader = Arc::new(reader);
+        let handler = Arc::new(handler);
+
+        // accept each connection and queue it for the workers
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    let reader = Arc::clone(&reader);
+                    let handler = Arc::clone(&handler);
+                    let on_error = Arc::clone(&self.on_error);
+                    if pool.submit(move || SockMonitor::handle(s, &*reader, &*handler, Reply::Close, &*on_error)).is_err() {
+                        (self.on_error)(MonitorError::new(Phase::Accept, "worker pool closed"));
+                    }
+                }
+                Err(e) => (self.on_error)(MonitorError::new(Phase::Accept, e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a newline terminated string
+    pub fn send_string(&self, msg: &str) -> Result<String, std::io::Error> {
+        SockMonitor::exchange_string(TcpStream::connect(&self.addr)?, msg)
+    }
+
+    /// Send a byte array
+    pub fn send_bytes(&self, msg: &[u8]) -> Result<String, std::io::Error> {
+        SockMonitor::exchange_bytes(TcpStream::connect(&self.addr)?, Framing::default(), msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time};
+
+    #[test]
+    fn test_tcp_mon() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        thread::spawn(move || {
+            let mut mon = TcpMonitor::new("127.0.0.1:47302");
+            mon.on_error(move |e| tx.lock().unwrap().send(e.phase()).unwrap());
+            mon.serve_concurrent(SockMonitor::read_bytes, move |req| match req.as_str() {
+                "fail" => Err("bad request".into()),
+                _ => Ok(format!("OK {}", req)),
+            }, 2).unwrap();
+        });
+
+        let client = TcpMonitor::new("127.0.0.1:47302");
+        let mut resp = client.send_bytes(b"hello");
+        while resp.is_err() {
+            thread::sleep(time::Duration::from_millis(100));
+            resp = client.send_bytes(b"hello");
+        }
+        assert_eq!(resp.unwrap(), "OK hello");
+        assert_eq!(client.send_bytes(b"world").unwrap(), "OK world");
+        assert_eq!(client.send_bytes(b"fail").unwrap(), "ERR: bad request");
+        assert_eq!(rx.recv().unwrap(), Phase::Handle);
+    }
+}